@@ -0,0 +1,207 @@
+use crate::{retry, Attempt, Backoff, Retry, Retryable};
+use pin_project::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+/// Retry a future made by `task` for every attempt until it succeeds, keeping the failures
+/// of all attempts.
+///
+/// Like `retry_with_attempt`, but `task` is also passed the `Notes` of the attempt to attach
+/// short annotations like `"endpoint=eu-1"` to. Attempts fail with `Failures`, whose
+/// `Display` lists every failed attempt so far along with its annotations, so the
+/// `RetryError` of a retry which gave up shows what each attempt tried.
+pub fn retry_annotated<F, Fut, I, E, S>(task: F, scheduler: S) -> Retry<Annotated<F>>
+where
+    F: Fn(&Attempt, Notes) -> Fut,
+    Fut: Future<Output = Result<I, E>>,
+    E: fmt::Debug + fmt::Display,
+    S: Backoff + 'static,
+{
+    retry(
+        Annotated {
+            task,
+            failures: Arc::new(Mutex::new(Vec::new())),
+        },
+        scheduler,
+    )
+}
+
+/// Retryable returned by `retry_annotated`
+pub struct Annotated<F> {
+    task: F,
+    failures: Arc<Mutex<Vec<Failure>>>,
+}
+
+impl<F, Fut, I, E> Retryable for Annotated<F>
+where
+    F: Fn(&Attempt, Notes) -> Fut,
+    Fut: Future<Output = Result<I, E>>,
+    E: fmt::Debug + fmt::Display,
+{
+    type Item = I;
+    type Error = Failures<E>;
+    type Future = AnnotatedAttempt<Fut>;
+
+    fn call(&self) -> Self::Future {
+        self.call_attempt(&Attempt {
+            number: 1,
+            elapsed: Duration::from_secs(0),
+            previous_delay: None,
+        })
+    }
+
+    fn call_attempt(&self, attempt: &Attempt) -> Self::Future {
+        if attempt.number == 1 {
+            self.failures.lock().unwrap().clear();
+        }
+        let notes = Notes::default();
+        AnnotatedAttempt {
+            fut: (self.task)(attempt, notes.clone()),
+            attempt: attempt.number,
+            notes,
+            failures: self.failures.clone(),
+        }
+    }
+}
+
+/// The annotations of one attempt, see `retry_annotated`.
+#[derive(Clone, Debug, Default)]
+pub struct Notes(Arc<Mutex<Vec<String>>>);
+
+impl Notes {
+    /// Attach `note` to the attempt, shown with its error if it fails.
+    pub fn annotate(&self, note: impl Into<String>) {
+        self.0.lock().unwrap().push(note.into());
+    }
+}
+
+/// A failed attempt of a `retry_annotated` task.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Failure {
+    /// The number of the attempt, starting at one.
+    pub attempt: u32,
+    /// The annotations the attempt attached.
+    pub notes: Vec<String>,
+    /// The error the attempt failed with.
+    pub error: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "attempt {}", self.attempt)?;
+        if !self.notes.is_empty() {
+            write!(f, " [{}]", self.notes.join(", "))?;
+        }
+        write!(f, ": {}", self.error)
+    }
+}
+
+/// The error of an attempt of a `retry_annotated` task, along with all failures so far.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Failures<E> {
+    /// The error the attempt failed with.
+    pub error: E,
+    /// The failed attempts so far, including this one.
+    pub failures: Vec<Failure>,
+}
+
+impl<E> fmt::Display for Failures<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)?;
+        for failure in &self.failures {
+            write!(f, "\n  {}", failure)?;
+        }
+        Ok(())
+    }
+}
+
+impl<E> std::error::Error for Failures<E>
+where
+    E: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Future returned by `Annotated::call_attempt`
+#[pin_project]
+pub struct AnnotatedAttempt<Fut> {
+    #[pin]
+    fut: Fut,
+    attempt: u32,
+    notes: Notes,
+    failures: Arc<Mutex<Vec<Failure>>>,
+}
+
+impl<Fut, I, E> Future for AnnotatedAttempt<Fut>
+where
+    Fut: Future<Output = Result<I, E>>,
+    E: fmt::Display,
+{
+    type Output = Result<I, Failures<E>>;
+
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        match this.fut.poll(ctx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Ok(item)) => Poll::Ready(Ok(item)),
+            Poll::Ready(Err(error)) => {
+                let mut failures = this.failures.lock().unwrap();
+                failures.push(Failure {
+                    attempt: *this.attempt,
+                    notes: this.notes.0.lock().unwrap().clone(),
+                    error: error.to_string(),
+                });
+                Poll::Ready(Err(Failures {
+                    error,
+                    failures: failures.clone(),
+                }))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block_on, instant, RetryError};
+
+    #[test]
+    fn test_retry_annotated() {
+        let res = block_on(retry_annotated(
+            |attempt: &Attempt, notes: Notes| {
+                let number = attempt.number;
+                async move {
+                    let endpoint = if number % 2 == 1 { "eu-1" } else { "us-1" };
+                    notes.annotate(format!("endpoint={}", endpoint));
+                    if number == 2 {
+                        notes.annotate("after token refresh");
+                    }
+                    Err::<(), _>("refused")
+                }
+            },
+            instant().num_attempts(3),
+        ));
+        let err = res.unwrap_err();
+        assert_eq!(err.attempts(), 3);
+        assert_eq!(err.last_error().unwrap().failures.len(), 3);
+        assert_eq!(
+            err.to_string(),
+            "gave up after 3 attempts: refused\n  \
+             attempt 1 [endpoint=eu-1]: refused\n  \
+             attempt 2 [endpoint=us-1, after token refresh]: refused\n  \
+             attempt 3 [endpoint=eu-1]: refused"
+        );
+        assert!(matches!(err, RetryError::Exhausted { .. }));
+    }
+}
@@ -0,0 +1,136 @@
+use crate::{instant, retry, Backoff, Retry, Retryable};
+use std::{
+    future::Future,
+    pin::Pin,
+    time::{Duration, Instant},
+};
+
+/// Future returned by `AsyncBackoff::next_delay`
+pub type NextRetry<'a> = Pin<Box<dyn Future<Output = Option<Duration>> + Send + 'a>>;
+
+/// A backoff whose decisions may consult async sources.
+///
+/// Like `Backoff`, but `next_delay` returns a future, so the delay can come from a remote
+/// rate-limit service, a distributed coordination key or a schedule stored in a database.
+/// Every `Backoff` is an `AsyncBackoff`. Use `retry_async_backoff` to retry with one.
+pub trait AsyncBackoff: Send {
+    /// Decide how long to wait before the next attempt, `None` to give up.
+    fn next_delay(&mut self) -> NextRetry<'_>;
+
+    /// Observe how long the last failed attempt took.
+    fn attempt_finished(&mut self, _duration: Duration) {}
+
+    /// Observe the current time of the retry's clock before `next_delay` is called.
+    fn deciding_at(&mut self, _now: Instant) {}
+}
+
+impl<B> AsyncBackoff for B
+where
+    B: Backoff,
+{
+    fn next_delay(&mut self) -> NextRetry<'_> {
+        let delay = self.next_retry();
+        Box::pin(async move { delay })
+    }
+
+    fn attempt_finished(&mut self, duration: Duration) {
+        self.observe_attempt(duration);
+    }
+
+    fn deciding_at(&mut self, now: Instant) {
+        self.observe_time(now);
+    }
+}
+
+/// Retry a future until it succeeds, awaiting `backoff` for the delay after every failure.
+///
+/// Returns a regular `Retry`, see `Retry::async_backoff`.
+pub fn retry_async_backoff<R, B>(task: R, backoff: B) -> Retry<R>
+where
+    R: Retryable,
+    R::Error: std::fmt::Debug,
+    B: AsyncBackoff + 'static,
+{
+    retry(task, instant()).async_backoff(backoff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block_on, ErrorKind, RetryError};
+    use futures_timer::Delay;
+    use std::sync::{Arc, Mutex};
+
+    /// Asks a (pretend) remote rate limiter for the delay.
+    struct RemoteLimiter {
+        budget: Arc<Mutex<Vec<Duration>>>,
+    }
+
+    impl AsyncBackoff for RemoteLimiter {
+        fn next_delay(&mut self) -> NextRetry<'_> {
+            let budget = self.budget.clone();
+            Box::pin(async move {
+                Delay::new(Duration::from_millis(1)).await;
+                budget.lock().unwrap().pop()
+            })
+        }
+    }
+
+    #[test]
+    fn test_retry_async_backoff() {
+        let calls = Arc::new(Mutex::new(0));
+        let counter = calls.clone();
+        let limiter = RemoteLimiter {
+            budget: Arc::new(Mutex::new(vec![Duration::from_millis(5); 2])),
+        };
+        let res = block_on(retry_async_backoff(
+            move || {
+                *counter.lock().unwrap() += 1;
+                async { Err::<(), _>("failed") }
+            },
+            limiter,
+        ));
+        assert!(res.is_err());
+        assert_eq!(*calls.lock().unwrap(), 3);
+
+        let calls = Arc::new(Mutex::new(0));
+        let retries = Arc::new(Mutex::new(Vec::new()));
+        let (counter, seen) = (calls.clone(), retries.clone());
+        let limiter = RemoteLimiter {
+            budget: Arc::new(Mutex::new(vec![Duration::from_millis(5); 5])),
+        };
+        let task = move || {
+            let mut calls = counter.lock().unwrap();
+            *calls += 1;
+            let status = if *calls < 3 { 503 } else { 404 };
+            async move { Err::<(), u16>(status) }
+        };
+        let res = block_on(
+            retry_async_backoff(task, limiter)
+                .classifier(|status: &u16| match status {
+                    404 => ErrorKind::Permanent,
+                    _ => ErrorKind::Transient,
+                })
+                .on_retry(move |attempt, delay| {
+                    seen.lock().unwrap().push((attempt.number, delay));
+                }),
+        );
+        assert_eq!(
+            res,
+            Err(RetryError::Aborted {
+                attempts: 3,
+                error: Some(404)
+            })
+        );
+        assert_eq!(
+            *retries.lock().unwrap(),
+            vec![(1, Duration::from_millis(5)), (2, Duration::from_millis(5))]
+        );
+
+        let res = block_on(retry_async_backoff(
+            || async { Ok::<_, ()>(1) },
+            instant().num_attempts(1),
+        ));
+        assert_eq!(res.unwrap(), 1);
+    }
+}
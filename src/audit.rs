@@ -0,0 +1,79 @@
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+/// Future returned by `AuditSink::write`
+pub type AuditWrite = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// A decision made by a `Retry`, written to its `AuditSink`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AuditRecord {
+    /// An attempt was started.
+    AttemptStarted { attempt: u32 },
+    /// An attempt failed with the error, formatted with `Debug`.
+    AttemptFailed { attempt: u32, error: String },
+    /// An attempt timed out.
+    AttemptTimedOut { attempt: u32, timeout: Duration },
+    /// The retry waits for `delay` before the next attempt.
+    Waiting { attempt: u32, delay: Duration },
+    /// An attempt succeeded.
+    Succeeded { attempt: u32 },
+    /// The backoff gave up.
+    Exhausted { attempt: u32 },
+    /// The retry was stopped for `reason` before the backoff gave up.
+    Aborted { attempt: u32, reason: &'static str },
+}
+
+/// Durable trail of the decisions of a `Retry`, installed with `Retry::audit`.
+///
+/// Every record is written in order. The retry doesn't start another attempt, and doesn't
+/// resolve, before all records written so far are done, so a slow sink holds the retry back
+/// instead of losing records.
+pub trait AuditSink: Send {
+    fn write(&mut self, record: AuditRecord) -> AuditWrite;
+}
+
+impl<F> AuditSink for F
+where
+    F: FnMut(AuditRecord) -> AuditWrite + Send,
+{
+    fn write(&mut self, record: AuditRecord) -> AuditWrite {
+        self(record)
+    }
+}
+
+/// The sink of a `Retry` and the writes it hasn't finished yet.
+pub(crate) struct Audit {
+    sink: Box<dyn AuditSink>,
+    writes: VecDeque<AuditWrite>,
+}
+
+impl Audit {
+    pub(crate) fn new(sink: Box<dyn AuditSink>) -> Self {
+        Audit {
+            sink,
+            writes: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn record(&mut self, record: AuditRecord) {
+        self.writes.push_back(self.sink.write(record));
+    }
+
+    /// Drive the pending writes, ready once all of them are done.
+    pub(crate) fn poll_flush(&mut self, ctx: &mut Context<'_>) -> Poll<()> {
+        while let Some(write) = self.writes.front_mut() {
+            match write.as_mut().poll(ctx) {
+                Poll::Ready(()) => {
+                    self.writes.pop_front();
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(())
+    }
+}
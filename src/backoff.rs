@@ -1,4 +1,7 @@
-use rand::{thread_rng, Rng};
+use rand::{
+    distributions::WeightedIndex, prelude::Distribution, rngs::SmallRng, thread_rng, Rng,
+    SeedableRng,
+};
 use std::time::{Duration, Instant};
 
 /// Make a zero delay backoff
@@ -11,17 +14,263 @@ pub fn constant(duration: Duration) -> impl Backoff + Sized {
     duration
 }
 
+/// Make a backoff producing the given delays in order and giving up after the last one
+pub fn sequence<I>(delays: I) -> Sequence
+where
+    I: IntoIterator<Item = Duration>,
+{
+    Sequence {
+        delays: delays.into_iter().collect::<Vec<_>>().into_iter(),
+    }
+}
+
+/// Make a backoff waiting `base` times the next fibonacci number, `1, 1, 2, 3, 5, 8, ..`
+pub fn fibonacci(base: Duration) -> Fibonacci {
+    Fibonacci {
+        base,
+        current: 1,
+        next: 1,
+    }
+}
+
+/// Make a backoff picking a uniformly random delay between `min` and `max` for every retry
+pub fn random_between(min: Duration, max: Duration) -> RandomBetween {
+    assert!(min <= max, "min must be smaller or equal to max");
+    RandomBetween {
+        min,
+        max,
+        rng: new_rng(),
+    }
+}
+
+/// Make a backoff with decorrelated jitter, picking every delay uniformly between `base` and
+/// three times the previous delay, capped at `cap`
+///
+/// Unlike exponential backoff with jitter, the delays of concurrent retries drift apart
+/// instead of growing in lockstep.
+pub fn decorrelated_jitter(base: Duration, cap: Duration) -> DecorrelatedJitter {
+    assert!(base <= cap, "base must be smaller or equal to cap");
+    DecorrelatedJitter {
+        base,
+        cap,
+        prev: base,
+        rng: new_rng(),
+    }
+}
+
+/// Make a backoff picking every delay from weighted candidates
+///
+/// `weighted(vec![(Duration::from_secs(1), 70), (Duration::from_secs(5), 25), (Duration::from_secs(30), 5)])`
+/// waits 1s for 70% of the retries, 5s for 25% and 30s for the remaining 5%.
+pub fn weighted<I>(candidates: I) -> Weighted
+where
+    I: IntoIterator<Item = (Duration, u32)>,
+{
+    let (delays, weights): (Vec<_>, Vec<_>) = candidates
+        .into_iter()
+        .filter(|(_, weight)| *weight > 0)
+        .unzip();
+    let index = WeightedIndex::new(&weights)
+        .expect("candidates must not be empty and weights must not all be zero");
+    Weighted {
+        delays,
+        index,
+        rng: new_rng(),
+    }
+}
+
+/// Make a backoff which starts over with a fresh backoff from `make` whenever the label of the
+/// errors changes
+///
+/// When failures flip from, say, `"rate_limited"` to `"connection_refused"`, the escalation so
+/// far was calibrated to a different failure mode. Requires labelled errors, see
+/// `Retry::label_errors`.
+///
+/// Attempt caps made by `make` start over as well, so alternating errors would retry forever.
+/// Cap the attempts outside instead, like `reset_on_error_change(make).num_attempts(10)`.
+pub fn reset_on_error_change<F, S>(make: F) -> ResetOnErrorChange<F, S>
+where
+    F: Fn() -> S + Send,
+    S: Backoff,
+{
+    ResetOnErrorChange {
+        inner: make(),
+        make,
+        label: None,
+    }
+}
+
+/// Make the random number generator owned by a randomized backoff.
+///
+/// Every backoff seeds its own generator once, which avoids the thread local lookup of
+/// `thread_rng()` on every retry. Delays don't need a cryptographically secure generator,
+/// so a small and fast one does.
+fn new_rng() -> SmallRng {
+    SmallRng::from_rng(thread_rng()).expect("failed to seed random number generator")
+}
+
+/// The maximum number of attempts and the cumulative delay of a backoff.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WorstCase {
+    /// Number of attempts, including the first one.
+    pub attempts: u32,
+    /// Sum of all delays between the attempts.
+    pub total_delay: Duration,
+}
+
+/// A dangerous backoff configuration found by `validate`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PolicyWarning {
+    /// The backoff never gives up: it has no attempt cap and no deadline.
+    Unbounded,
+    /// The backoff retries without any delay and has no attempt cap.
+    ZeroDelayUnbounded,
+    /// Jitter is applied to a zero base delay.
+    JitterOnZeroBase,
+}
+
+impl std::fmt::Display for PolicyWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PolicyWarning::Unbounded => {
+                write!(f, "retries forever without attempt cap or deadline")
+            }
+            PolicyWarning::ZeroDelayUnbounded => {
+                write!(f, "retries without delay and without attempt cap")
+            }
+            PolicyWarning::JitterOnZeroBase => write!(f, "jitter is applied to a zero delay"),
+        }
+    }
+}
+
+/// The structure of a backoff as collected by `Backoff::inspect`.
+#[derive(Clone, Debug, Default)]
+pub struct Inspection {
+    /// The number of attempts is capped.
+    pub attempts_capped: bool,
+    /// The backoff gives up at a deadline.
+    pub has_deadline: bool,
+    /// The earliest deadline the backoff gives up at.
+    pub deadline: Option<Instant>,
+    /// The delay produced so far in the chain is always zero.
+    pub zero_delay: bool,
+    /// Warnings found while inspecting the chain.
+    pub warnings: Vec<PolicyWarning>,
+}
+
 pub trait Backoff: Send {
     /// Get the duration to wait for before attempting again
     fn next_retry(&mut self) -> Option<Duration>;
 
+    /// Get the largest duration `next_retry` could return.
+    ///
+    /// This advances the backoff just like `next_retry`. Randomized backoffs must override
+    /// this to return their upper bound.
+    fn max_retry(&mut self) -> Option<Duration> {
+        self.next_retry()
+    }
+
+    /// Observe how long the failed attempt took before `next_retry` is called.
+    ///
+    /// Combinators must forward this to their inner backoff.
+    fn observe_attempt(&mut self, _duration: Duration) {}
+
+    /// Observe the label of the error the failed attempt failed with.
+    ///
+    /// Called before `observe_attempt` when errors are labelled (see `Retry::label_errors`) and
+    /// with `"timeout"` for attempts that timed out. Combinators must forward this to their
+    /// inner backoff.
+    fn observe_error(&mut self, _label: &str) {}
+
+    /// Observe the current time of the retry's clock right before `next_retry` is called.
+    ///
+    /// Lets time based backoffs like `deadline` follow a `ManualClock` or a custom `Sleeper`.
+    /// Combinators must forward this to their inner backoff.
+    fn observe_time(&mut self, _now: Instant) {}
+
+    /// Record the structure of this backoff for `validate`.
+    ///
+    /// Combinators must inspect their inner backoff first.
+    fn inspect(&self, _inspection: &mut Inspection) {}
+
+    /// Check the backoff for obviously dangerous configurations.
+    ///
+    /// An empty list means no problems were found.
+    fn validate(&self) -> Vec<PolicyWarning> {
+        let mut inspection = Inspection::default();
+        self.inspect(&mut inspection);
+        let mut warnings = inspection.warnings;
+        if !inspection.attempts_capped {
+            if inspection.zero_delay {
+                warnings.push(PolicyWarning::ZeroDelayUnbounded);
+            }
+            if !inspection.has_deadline {
+                warnings.push(PolicyWarning::Unbounded);
+            }
+        }
+        warnings
+    }
+
+    /// Compute the worst case number of attempts and cumulative delay.
+    ///
+    /// Returns `None` when the backoff doesn't give up within `limit` retries. Deadlines are
+    /// evaluated against the current time plus the delays so far, and the last delay is cut
+    /// short at the deadline just like `Retry` does.
+    fn worst_case_total(mut self, limit: u32) -> Option<WorstCase>
+    where
+        Self: Sized,
+    {
+        let mut inspection = Inspection::default();
+        self.inspect(&mut inspection);
+        let start = Instant::now();
+        let mut total_delay = Duration::from_secs(0);
+        for retries in 0..=limit {
+            let now = start.checked_add(total_delay);
+            if let Some(now) = now {
+                self.observe_time(now);
+            }
+            let left = inspection
+                .deadline
+                .zip(now)
+                .and_then(|(deadline, now)| deadline.checked_duration_since(now));
+            match self.max_retry() {
+                Some(dur) => match left {
+                    Some(left) if left < dur => {
+                        return Some(WorstCase {
+                            attempts: retries + 1,
+                            total_delay: total_delay + left,
+                        })
+                    }
+                    _ => total_delay = total_delay.saturating_add(dur),
+                },
+                None => {
+                    return Some(WorstCase {
+                        attempts: retries + 1,
+                        total_delay,
+                    })
+                }
+            }
+        }
+        None
+    }
+
     /// Grow the backoff duration exponentially
     fn exponential(self) -> Exponential<Self>
     where
         Self: Sized,
     {
+        self.exponential_by(2)
+    }
+
+    /// Grow the backoff duration exponentially by multiplying it with `base` every retry
+    fn exponential_by(self, base: u32) -> Exponential<Self>
+    where
+        Self: Sized,
+    {
+        assert!(base > 0, "base must be larger than zero");
         Exponential {
             factor: 1,
+            base,
             inner: self,
         }
     }
@@ -45,24 +294,44 @@ pub trait Backoff: Send {
     /// Randomize the backoff duration.
     ///
     /// The returned duration will never be larger than the base duration and will
-    /// never be smaller than `base * (1.0 - scale)`.
+    /// never be smaller than `base * (1.0 - scale)`. Zero and tiny bases, which leave no
+    /// room to randomize, are returned as is. Use `Jitter::min_delay` to keep jittered
+    /// delays from getting arbitrarily close to zero.
     fn jitter(self, scale: f64) -> Jitter<Self>
     where
         Self: Sized,
     {
         assert!(scale > 0.0, "scale must be larger than zero");
         assert!(scale <= 1.0, "scale must be smaller or equal to one");
-        Jitter { scale, inner: self }
+        Jitter {
+            scale,
+            min_delay: Duration::from_secs(0),
+            rng: new_rng(),
+            inner: self,
+        }
     }
 
+    /// Limit the total number of attempts, including the first one.
+    ///
+    /// `num_attempts(3)` makes the first attempt and at most 2 retries.
     fn num_attempts(self, num: u32) -> MaxAttempts<Self>
     where
         Self: Sized,
     {
         assert!(num > 0, "num must be larger than zero");
-        let num_attempts_left = num - 1;
+        self.max_retries(num - 1)
+    }
+
+    /// Limit the number of retries after the first attempt.
+    ///
+    /// `max_retries(3)` makes the first attempt and at most 3 retries, `max_retries(0)`
+    /// never retries.
+    fn max_retries(self, num: u32) -> MaxAttempts<Self>
+    where
+        Self: Sized,
+    {
         MaxAttempts {
-            num_attempts_left,
+            num_attempts_left: num,
             inner: self,
         }
     }
@@ -73,6 +342,140 @@ pub trait Backoff: Send {
     {
         Deadline {
             deadline,
+            now: None,
+            inner: self,
+        }
+    }
+
+    /// Use a different backoff for attempts that failed fast.
+    ///
+    /// Attempts that failed within `threshold` (like a refused connection) wait according to
+    /// `fast`, slower failures (like a timeout) according to this backoff.
+    fn fail_fast<F>(self, threshold: Duration, fast: F) -> FailFast<Self, F>
+    where
+        Self: Sized,
+        F: Backoff,
+    {
+        FailFast {
+            threshold,
+            last_attempt: None,
+            fast,
+            inner: self,
+        }
+    }
+
+    /// Give up once `stop` returns true.
+    ///
+    /// The condition is checked whenever a retry is scheduled, so a shutdown flag or a
+    /// `tokio::sync::watch::Receiver<bool>` (`move || *rx.borrow()`) ends the retry loop at
+    /// the next failure.
+    fn stop_when<F>(self, stop: F) -> StopWhen<Self, F>
+    where
+        Self: Sized,
+        F: Fn() -> bool + Send,
+    {
+        StopWhen { stop, inner: self }
+    }
+
+    /// Round the backoff duration up to a multiple of `step`.
+    fn quantize(self, step: Duration) -> Quantize<Self>
+    where
+        Self: Sized,
+    {
+        assert!(
+            step > Duration::from_secs(0),
+            "step must be larger than zero"
+        );
+        Quantize { step, inner: self }
+    }
+
+    /// Retry the first failure immediately, then continue with this backoff.
+    ///
+    /// Apply this before `num_attempts` or `deadline` to have the immediate retry count
+    /// against them.
+    fn immediate_first_retry(self) -> Warmup<Self>
+    where
+        Self: Sized,
+    {
+        self.warmup(1, Duration::from_secs(0))
+    }
+
+    /// Retry `num` times after `delay`, then continue with this backoff.
+    ///
+    /// Models the "retry quickly a few times, then back off hard" shape. Apply this before
+    /// `num_attempts` or `deadline` to have the warmup retries count against them.
+    fn warmup(self, num: u32, delay: Duration) -> Warmup<Self>
+    where
+        Self: Sized,
+    {
+        Warmup {
+            num_left: num,
+            delay,
+            inner: self,
+        }
+    }
+
+    /// Add a stable offset within `window` to the first delay, derived from `id`.
+    ///
+    /// Give every instance of a fleet its own `id` (like its host name) so replicas restarting
+    /// at the same time don't all retry a shared dependency at the same instant. Unlike jitter
+    /// the offset of an instance is the same on every run.
+    fn splay<T>(self, window: Duration, id: T) -> Splay<Self>
+    where
+        Self: Sized,
+        T: std::hash::Hash,
+    {
+        let mut hasher = Fnv1a::default();
+        id.hash(&mut hasher);
+        let window = window.as_nanos() as u64;
+        let offset = match window {
+            0 => 0,
+            _ => std::hash::Hasher::finish(&hasher) % window,
+        };
+        Splay {
+            offset: Some(Duration::from_nanos(offset)),
+            inner: self,
+        }
+    }
+
+    /// Make at least `num` attempts, including the first one, even after this backoff gave up.
+    ///
+    /// For operations where giving up early isn't an option, like persisting at least twice
+    /// even though the deadline has passed. Once this backoff gave up the remaining attempts
+    /// wait for the last delay it returned, but at least for `floor`, so a backoff which gave
+    /// up right away doesn't retry in a busy loop.
+    fn min_attempts(self, num: u32, floor: Duration) -> MinAttempts<Self>
+    where
+        Self: Sized,
+    {
+        assert!(num > 0, "num must be larger than zero");
+        MinAttempts {
+            min_retries_left: num - 1,
+            guaranteed: false,
+            last: Duration::from_secs(0),
+            floor,
+            inner: self,
+        }
+    }
+
+    /// Randomize the backoff duration by a stable hash of `key` and the retry number.
+    ///
+    /// Delays stay within the same bounds as `jitter`, but the same key always produces the
+    /// same schedule. Different keys (like host names or request ids) still spread their
+    /// retries, so schedules are reproducible in tests and when debugging production timing.
+    fn jitter_keyed<T>(self, scale: f64, key: T) -> KeyedJitter<Self>
+    where
+        Self: Sized,
+        T: std::hash::Hash,
+    {
+        assert!(scale > 0.0, "scale must be larger than zero");
+        assert!(scale <= 1.0, "scale must be smaller or equal to one");
+        let mut hasher = Fnv1a::default();
+        key.hash(&mut hasher);
+        KeyedJitter {
+            scale,
+            key: std::hash::Hasher::finish(&hasher),
+            retry: 0,
             inner: self,
         }
     }
@@ -82,124 +485,950 @@ impl Backoff for Duration {
     fn next_retry(&mut self) -> Option<Duration> {
         Some(*self)
     }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        inspection.zero_delay = *self == Duration::from_secs(0);
+    }
+}
+
+impl<B> Backoff for Box<B>
+where
+    B: Backoff + ?Sized,
+{
+    fn next_retry(&mut self) -> Option<Duration> {
+        (**self).next_retry()
+    }
+
+    fn max_retry(&mut self) -> Option<Duration> {
+        (**self).max_retry()
+    }
+
+    fn observe_attempt(&mut self, duration: Duration) {
+        (**self).observe_attempt(duration);
+    }
+
+    fn observe_error(&mut self, label: &str) {
+        (**self).observe_error(label);
+    }
+
+    fn observe_time(&mut self, now: Instant) {
+        (**self).observe_time(now);
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        (**self).inspect(inspection);
+    }
+}
+
+pub struct Sequence {
+    delays: std::vec::IntoIter<Duration>,
+}
+
+impl Backoff for Sequence {
+    fn next_retry(&mut self) -> Option<Duration> {
+        self.delays.next()
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        let zero = Duration::from_secs(0);
+        inspection.attempts_capped = true;
+        inspection.zero_delay = self.delays.as_slice().iter().all(|dur| *dur == zero);
+    }
+}
+
+pub struct Fibonacci {
+    base: Duration,
+    current: u32,
+    next: u32,
+}
+
+impl Backoff for Fibonacci {
+    fn next_retry(&mut self) -> Option<Duration> {
+        let delay = self.base.saturating_mul(self.current);
+        let next = self.current.saturating_add(self.next);
+        self.current = self.next;
+        self.next = next;
+        Some(delay)
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        inspection.zero_delay = self.base == Duration::from_secs(0);
+    }
+}
+
+pub struct RandomBetween {
+    min: Duration,
+    max: Duration,
+    rng: SmallRng,
+}
+
+impl RandomBetween {
+    /// Seed the random number generator, making the delays reproducible.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng = SmallRng::seed_from_u64(seed);
+        self
+    }
+}
+
+impl Backoff for RandomBetween {
+    fn next_retry(&mut self) -> Option<Duration> {
+        if self.min == self.max {
+            Some(self.min)
+        } else {
+            Some(self.rng.gen_range(self.min, self.max))
+        }
+    }
+
+    fn max_retry(&mut self) -> Option<Duration> {
+        Some(self.max)
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        inspection.zero_delay = self.max == Duration::from_secs(0);
+    }
+}
+
+pub struct DecorrelatedJitter {
+    base: Duration,
+    cap: Duration,
+    prev: Duration,
+    rng: SmallRng,
+}
+
+impl DecorrelatedJitter {
+    /// Seed the random number generator, making the delays reproducible.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng = SmallRng::seed_from_u64(seed);
+        self
+    }
+}
+
+impl Backoff for DecorrelatedJitter {
+    fn next_retry(&mut self) -> Option<Duration> {
+        let high = self.prev.saturating_mul(3).min(self.cap);
+        let delay = if self.base < high {
+            self.rng.gen_range(self.base, high)
+        } else {
+            high
+        };
+        self.prev = delay;
+        Some(delay)
+    }
+
+    fn max_retry(&mut self) -> Option<Duration> {
+        Some(self.cap)
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        inspection.zero_delay = self.cap == Duration::from_secs(0);
+    }
+}
+
+pub struct Weighted {
+    delays: Vec<Duration>,
+    index: WeightedIndex<u32>,
+    rng: SmallRng,
+}
+
+impl Weighted {
+    /// Seed the random number generator, making the delays reproducible.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng = SmallRng::seed_from_u64(seed);
+        self
+    }
+}
+
+impl Backoff for Weighted {
+    fn next_retry(&mut self) -> Option<Duration> {
+        Some(self.delays[self.index.sample(&mut self.rng)])
+    }
+
+    fn max_retry(&mut self) -> Option<Duration> {
+        self.delays.iter().max().copied()
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        let zero = Duration::from_secs(0);
+        inspection.zero_delay = self.delays.iter().all(|dur| *dur == zero);
+    }
+}
+
+pub struct Exponential<S>
+where
+    S: Backoff,
+{
+    inner: S,
+    factor: u32,
+    base: u32,
+}
+
+impl<S> Backoff for Exponential<S>
+where
+    S: Backoff,
+{
+    fn next_retry(&mut self) -> Option<Duration> {
+        let dur = self.inner.next_retry();
+        self.grow(dur)
+    }
+
+    fn max_retry(&mut self) -> Option<Duration> {
+        let dur = self.inner.max_retry();
+        self.grow(dur)
+    }
+
+    fn observe_attempt(&mut self, duration: Duration) {
+        self.inner.observe_attempt(duration);
+    }
+
+    fn observe_error(&mut self, label: &str) {
+        self.inner.observe_error(label);
+    }
+
+    fn observe_time(&mut self, now: Instant) {
+        self.inner.observe_time(now);
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        self.inner.inspect(inspection);
+    }
+}
+
+impl<S> Exponential<S>
+where
+    S: Backoff,
+{
+    fn grow(&mut self, dur: Option<Duration>) -> Option<Duration> {
+        let dur = dur.map(|dur| dur.checked_mul(self.factor).unwrap_or(Duration::MAX));
+        self.factor = self.factor.saturating_mul(self.base);
+        dur
+    }
+}
+
+pub struct Max<S>
+where
+    S: Backoff,
+{
+    inner: S,
+    max: Duration,
+}
+
+impl<S> Backoff for Max<S>
+where
+    S: Backoff,
+{
+    fn next_retry(&mut self) -> Option<Duration> {
+        self.inner
+            .next_retry()
+            .map(|dur| std::cmp::min(self.max, dur))
+    }
+
+    fn max_retry(&mut self) -> Option<Duration> {
+        self.inner
+            .max_retry()
+            .map(|dur| std::cmp::min(self.max, dur))
+    }
+
+    fn observe_attempt(&mut self, duration: Duration) {
+        self.inner.observe_attempt(duration);
+    }
+
+    fn observe_error(&mut self, label: &str) {
+        self.inner.observe_error(label);
+    }
+
+    fn observe_time(&mut self, now: Instant) {
+        self.inner.observe_time(now);
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        self.inner.inspect(inspection);
+        if self.max == Duration::from_secs(0) {
+            inspection.zero_delay = true;
+        }
+    }
+}
+
+pub struct Min<S>
+where
+    S: Backoff,
+{
+    inner: S,
+    min: Duration,
+}
+
+impl<S> Backoff for Min<S>
+where
+    S: Backoff,
+{
+    fn next_retry(&mut self) -> Option<Duration> {
+        self.inner
+            .next_retry()
+            .map(|dur| std::cmp::max(self.min, dur))
+    }
+
+    fn max_retry(&mut self) -> Option<Duration> {
+        self.inner
+            .max_retry()
+            .map(|dur| std::cmp::max(self.min, dur))
+    }
+
+    fn observe_attempt(&mut self, duration: Duration) {
+        self.inner.observe_attempt(duration);
+    }
+
+    fn observe_error(&mut self, label: &str) {
+        self.inner.observe_error(label);
+    }
+
+    fn observe_time(&mut self, now: Instant) {
+        self.inner.observe_time(now);
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        self.inner.inspect(inspection);
+        if self.min > Duration::from_secs(0) {
+            inspection.zero_delay = false;
+        }
+    }
+}
+
+pub struct Jitter<S>
+where
+    S: Backoff,
+{
+    inner: S,
+    scale: f64,
+    min_delay: Duration,
+    rng: SmallRng,
+}
+
+impl<S> Jitter<S>
+where
+    S: Backoff,
+{
+    /// Seed the random number generator, making the delays reproducible.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng = SmallRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Never return a delay shorter than `min`, even if the base is shorter.
+    ///
+    /// Keeps zero and sub-millisecond bases from turning into busy retries.
+    pub fn min_delay(mut self, min: Duration) -> Self {
+        self.min_delay = min;
+        self
+    }
+}
+
+impl<S> Backoff for Jitter<S>
+where
+    S: Backoff,
+{
+    fn next_retry(&mut self) -> Option<Duration> {
+        let scale = self.scale;
+        let rng = &mut self.rng;
+        let delay = self.inner.next_retry().map(|dur| {
+            let margin =
+                Duration::try_from_secs_f64(dur.as_secs_f64() * scale).unwrap_or(Duration::MAX);
+            let low = dur.saturating_sub(margin);
+            if low < dur {
+                rng.gen_range(low, dur)
+            } else {
+                dur
+            }
+        });
+        delay.map(|dur| std::cmp::max(dur, self.min_delay))
+    }
+
+    fn max_retry(&mut self) -> Option<Duration> {
+        let delay = self.inner.max_retry();
+        delay.map(|dur| std::cmp::max(dur, self.min_delay))
+    }
+
+    fn observe_attempt(&mut self, duration: Duration) {
+        self.inner.observe_attempt(duration);
+    }
+
+    fn observe_error(&mut self, label: &str) {
+        self.inner.observe_error(label);
+    }
+
+    fn observe_time(&mut self, now: Instant) {
+        self.inner.observe_time(now);
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        self.inner.inspect(inspection);
+        if inspection.zero_delay {
+            inspection.warnings.push(PolicyWarning::JitterOnZeroBase);
+        }
+    }
+}
+
+pub struct MaxAttempts<S>
+where
+    S: Backoff,
+{
+    inner: S,
+    num_attempts_left: u32,
+}
+
+impl<S> Backoff for MaxAttempts<S>
+where
+    S: Backoff,
+{
+    fn next_retry(&mut self) -> Option<Duration> {
+        if self.num_attempts_left > 0 {
+            self.num_attempts_left -= 1;
+            self.inner.next_retry()
+        } else {
+            None
+        }
+    }
+
+    fn max_retry(&mut self) -> Option<Duration> {
+        if self.num_attempts_left > 0 {
+            self.num_attempts_left -= 1;
+            self.inner.max_retry()
+        } else {
+            None
+        }
+    }
+
+    fn observe_attempt(&mut self, duration: Duration) {
+        self.inner.observe_attempt(duration);
+    }
+
+    fn observe_error(&mut self, label: &str) {
+        self.inner.observe_error(label);
+    }
+
+    fn observe_time(&mut self, now: Instant) {
+        self.inner.observe_time(now);
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        self.inner.inspect(inspection);
+        inspection.attempts_capped = true;
+    }
+}
+
+pub struct Deadline<S>
+where
+    S: Backoff,
+{
+    inner: S,
+    deadline: Instant,
+    /// The time last passed to `observe_time`, the system time is used until then.
+    now: Option<Instant>,
+}
+
+impl<S> Deadline<S>
+where
+    S: Backoff,
+{
+    fn expired(&self) -> bool {
+        self.deadline < self.now.unwrap_or_else(Instant::now)
+    }
+}
+
+impl<S> Backoff for Deadline<S>
+where
+    S: Backoff,
+{
+    fn next_retry(&mut self) -> Option<Duration> {
+        if self.expired() {
+            None
+        } else {
+            self.inner.next_retry()
+        }
+    }
+
+    fn max_retry(&mut self) -> Option<Duration> {
+        if self.expired() {
+            None
+        } else {
+            self.inner.max_retry()
+        }
+    }
+
+    fn observe_attempt(&mut self, duration: Duration) {
+        self.inner.observe_attempt(duration);
+    }
+
+    fn observe_error(&mut self, label: &str) {
+        self.inner.observe_error(label);
+    }
+
+    fn observe_time(&mut self, now: Instant) {
+        self.now = Some(now);
+        self.inner.observe_time(now);
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        self.inner.inspect(inspection);
+        inspection.has_deadline = true;
+        inspection.deadline = match inspection.deadline {
+            Some(deadline) => Some(std::cmp::min(deadline, self.deadline)),
+            None => Some(self.deadline),
+        };
+    }
+}
+
+pub struct FailFast<S, F>
+where
+    S: Backoff,
+    F: Backoff,
+{
+    inner: S,
+    fast: F,
+    threshold: Duration,
+    last_attempt: Option<Duration>,
+}
+
+impl<S, F> FailFast<S, F>
+where
+    S: Backoff,
+    F: Backoff,
+{
+    fn failed_fast(&self) -> bool {
+        match self.last_attempt {
+            Some(duration) => duration < self.threshold,
+            None => false,
+        }
+    }
+}
+
+impl<S, F> Backoff for FailFast<S, F>
+where
+    S: Backoff,
+    F: Backoff,
+{
+    fn next_retry(&mut self) -> Option<Duration> {
+        if self.failed_fast() {
+            self.fast.next_retry()
+        } else {
+            self.inner.next_retry()
+        }
+    }
+
+    fn max_retry(&mut self) -> Option<Duration> {
+        match (self.inner.max_retry(), self.fast.max_retry()) {
+            (Some(a), Some(b)) => Some(std::cmp::max(a, b)),
+            (dur, None) | (None, dur) => dur,
+        }
+    }
+
+    fn observe_attempt(&mut self, duration: Duration) {
+        self.last_attempt = Some(duration);
+        self.inner.observe_attempt(duration);
+        self.fast.observe_attempt(duration);
+    }
+
+    fn observe_error(&mut self, label: &str) {
+        self.inner.observe_error(label);
+        self.fast.observe_error(label);
+    }
+
+    fn observe_time(&mut self, now: Instant) {
+        self.inner.observe_time(now);
+        self.fast.observe_time(now);
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        let mut fast = Inspection::default();
+        self.inner.inspect(inspection);
+        self.fast.inspect(&mut fast);
+        inspection.attempts_capped &= fast.attempts_capped;
+        inspection.has_deadline &= fast.has_deadline;
+        inspection.deadline = match (inspection.deadline, fast.deadline) {
+            (Some(a), Some(b)) => Some(std::cmp::max(a, b)),
+            _ => None,
+        };
+        inspection.zero_delay |= fast.zero_delay;
+        inspection.warnings.extend(fast.warnings);
+    }
+}
+
+pub struct StopWhen<S, F>
+where
+    S: Backoff,
+    F: Fn() -> bool + Send,
+{
+    inner: S,
+    stop: F,
+}
+
+impl<S, F> Backoff for StopWhen<S, F>
+where
+    S: Backoff,
+    F: Fn() -> bool + Send,
+{
+    fn next_retry(&mut self) -> Option<Duration> {
+        if (self.stop)() {
+            None
+        } else {
+            self.inner.next_retry()
+        }
+    }
+
+    fn max_retry(&mut self) -> Option<Duration> {
+        if (self.stop)() {
+            None
+        } else {
+            self.inner.max_retry()
+        }
+    }
+
+    fn observe_attempt(&mut self, duration: Duration) {
+        self.inner.observe_attempt(duration);
+    }
+
+    fn observe_error(&mut self, label: &str) {
+        self.inner.observe_error(label);
+    }
+
+    fn observe_time(&mut self, now: Instant) {
+        self.inner.observe_time(now);
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        self.inner.inspect(inspection);
+    }
 }
 
-pub struct Exponential<S>
+pub struct Quantize<S>
 where
     S: Backoff,
 {
     inner: S,
-    factor: u32,
+    step: Duration,
 }
 
-impl<S> Backoff for Exponential<S>
+impl<S> Quantize<S>
+where
+    S: Backoff,
+{
+    fn round_up(&self, dur: Duration) -> Duration {
+        let step = self.step.as_nanos();
+        let steps = dur.as_nanos().div_ceil(step);
+        let nanos = steps.saturating_mul(step);
+        if nanos > u64::MAX as u128 {
+            Duration::MAX
+        } else {
+            Duration::from_nanos(nanos as u64)
+        }
+    }
+}
+
+impl<S> Backoff for Quantize<S>
 where
     S: Backoff,
 {
     fn next_retry(&mut self) -> Option<Duration> {
-        let dur = self.inner.next_retry().map(|dur| dur * (self.factor as _));
-        self.factor *= 2;
-        dur
+        self.inner.next_retry().map(|dur| self.round_up(dur))
+    }
+
+    fn max_retry(&mut self) -> Option<Duration> {
+        self.inner.max_retry().map(|dur| self.round_up(dur))
+    }
+
+    fn observe_attempt(&mut self, duration: Duration) {
+        self.inner.observe_attempt(duration);
+    }
+
+    fn observe_error(&mut self, label: &str) {
+        self.inner.observe_error(label);
+    }
+
+    fn observe_time(&mut self, now: Instant) {
+        self.inner.observe_time(now);
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        self.inner.inspect(inspection);
     }
 }
 
-pub struct Max<S>
+pub struct Warmup<S>
 where
     S: Backoff,
 {
     inner: S,
-    max: Duration,
+    num_left: u32,
+    delay: Duration,
 }
 
-impl<S> Backoff for Max<S>
+impl<S> Backoff for Warmup<S>
 where
     S: Backoff,
 {
     fn next_retry(&mut self) -> Option<Duration> {
-        self.inner
-            .next_retry()
-            .map(|dur| std::cmp::min(self.max, dur))
+        if self.num_left > 0 {
+            self.num_left -= 1;
+            Some(self.delay)
+        } else {
+            self.inner.next_retry()
+        }
+    }
+
+    fn max_retry(&mut self) -> Option<Duration> {
+        if self.num_left > 0 {
+            self.num_left -= 1;
+            Some(self.delay)
+        } else {
+            self.inner.max_retry()
+        }
+    }
+
+    fn observe_attempt(&mut self, duration: Duration) {
+        self.inner.observe_attempt(duration);
+    }
+
+    fn observe_error(&mut self, label: &str) {
+        self.inner.observe_error(label);
+    }
+
+    fn observe_time(&mut self, now: Instant) {
+        self.inner.observe_time(now);
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        self.inner.inspect(inspection);
     }
 }
 
-pub struct Min<S>
+/// FNV-1a, a hash which is stable across processes and Rust versions.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl std::hash::Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+pub struct Splay<S>
 where
     S: Backoff,
 {
     inner: S,
-    min: Duration,
+    offset: Option<Duration>,
 }
 
-impl<S> Backoff for Min<S>
+impl<S> Backoff for Splay<S>
 where
     S: Backoff,
 {
     fn next_retry(&mut self) -> Option<Duration> {
-        self.inner
-            .next_retry()
-            .map(|dur| std::cmp::max(self.min, dur))
+        let delay = self.inner.next_retry()?;
+        match self.offset.take() {
+            Some(offset) => Some(delay.saturating_add(offset)),
+            None => Some(delay),
+        }
+    }
+
+    fn max_retry(&mut self) -> Option<Duration> {
+        let delay = self.inner.max_retry()?;
+        match self.offset.take() {
+            Some(offset) => Some(delay.saturating_add(offset)),
+            None => Some(delay),
+        }
+    }
+
+    fn observe_attempt(&mut self, duration: Duration) {
+        self.inner.observe_attempt(duration);
+    }
+
+    fn observe_error(&mut self, label: &str) {
+        self.inner.observe_error(label);
+    }
+
+    fn observe_time(&mut self, now: Instant) {
+        self.inner.observe_time(now);
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        self.inner.inspect(inspection);
     }
 }
 
-pub struct Jitter<S>
+pub struct ResetOnErrorChange<F, S>
 where
+    F: Fn() -> S + Send,
     S: Backoff,
 {
     inner: S,
-    scale: f64,
+    make: F,
+    label: Option<String>,
 }
 
-impl<S> Backoff for Jitter<S>
+impl<F, S> Backoff for ResetOnErrorChange<F, S>
 where
+    F: Fn() -> S + Send,
     S: Backoff,
 {
     fn next_retry(&mut self) -> Option<Duration> {
-        self.inner.next_retry().map(|dur| {
-            let margin = Duration::from_secs_f64(dur.as_secs_f64() * self.scale);
-            thread_rng().gen_range(dur - margin, dur)
-        })
+        self.inner.next_retry()
+    }
+
+    fn max_retry(&mut self) -> Option<Duration> {
+        self.inner.max_retry()
+    }
+
+    fn observe_attempt(&mut self, duration: Duration) {
+        self.inner.observe_attempt(duration);
+    }
+
+    fn observe_error(&mut self, label: &str) {
+        match &self.label {
+            Some(last) if last == label => {}
+            Some(_) => {
+                self.inner = (self.make)();
+                self.label = Some(label.to_string());
+            }
+            None => self.label = Some(label.to_string()),
+        }
+        self.inner.observe_error(label);
+    }
+
+    fn observe_time(&mut self, now: Instant) {
+        self.inner.observe_time(now);
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        self.inner.inspect(inspection);
+        // Caps of the inner backoff start over on every reset.
+        inspection.attempts_capped = false;
     }
 }
 
-pub struct MaxAttempts<S>
+pub struct MinAttempts<S>
 where
     S: Backoff,
 {
     inner: S,
-    num_attempts_left: u32,
+    min_retries_left: u32,
+    guaranteed: bool,
+    last: Duration,
+    floor: Duration,
 }
 
-impl<S> Backoff for MaxAttempts<S>
+impl<S> Backoff for MinAttempts<S>
 where
     S: Backoff,
 {
     fn next_retry(&mut self) -> Option<Duration> {
-        if self.num_attempts_left > 0 {
-            self.num_attempts_left -= 1;
-            self.inner.next_retry()
-        } else {
-            None
+        let delay = self.inner.next_retry();
+        self.guarantee(delay)
+    }
+
+    fn max_retry(&mut self) -> Option<Duration> {
+        let delay = self.inner.max_retry();
+        self.guarantee(delay)
+    }
+
+    fn observe_attempt(&mut self, duration: Duration) {
+        self.inner.observe_attempt(duration);
+    }
+
+    fn observe_error(&mut self, label: &str) {
+        self.inner.observe_error(label);
+    }
+
+    fn observe_time(&mut self, now: Instant) {
+        self.inner.observe_time(now);
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        self.inner.inspect(inspection);
+        if self.guaranteed {
+            // The last delay was guaranteed, the deadline must not cut it short.
+            inspection.deadline = None;
         }
     }
 }
 
-pub struct Deadline<S>
+impl<S> MinAttempts<S>
+where
+    S: Backoff,
+{
+    fn guarantee(&mut self, delay: Option<Duration>) -> Option<Duration> {
+        self.guaranteed = self.min_retries_left > 0;
+        self.min_retries_left = self.min_retries_left.saturating_sub(1);
+        match delay {
+            Some(delay) => {
+                self.last = delay;
+                Some(delay)
+            }
+            None if self.guaranteed => Some(std::cmp::max(self.last, self.floor)),
+            None => None,
+        }
+    }
+}
+
+pub struct KeyedJitter<S>
 where
     S: Backoff,
 {
     inner: S,
-    deadline: Instant,
+    scale: f64,
+    key: u64,
+    retry: u32,
 }
 
-impl<S> Backoff for Deadline<S>
+impl<S> Backoff for KeyedJitter<S>
 where
     S: Backoff,
 {
     fn next_retry(&mut self) -> Option<Duration> {
-        if self.deadline < Instant::now() {
-            None
-        } else {
-            self.inner.next_retry()
+        let dur = self.inner.next_retry()?;
+        let mut hasher = Fnv1a::default();
+        std::hash::Hash::hash(&(self.key, self.retry), &mut hasher);
+        self.retry = self.retry.wrapping_add(1);
+        let fraction = (std::hash::Hasher::finish(&hasher) % 1_000_000) as f64 / 1_000_000.0;
+        let margin = dur.as_secs_f64() * self.scale * fraction;
+        Some(
+            dur - Duration::try_from_secs_f64(margin)
+                .unwrap_or(Duration::MAX)
+                .min(dur),
+        )
+    }
+
+    fn max_retry(&mut self) -> Option<Duration> {
+        self.retry = self.retry.wrapping_add(1);
+        self.inner.max_retry()
+    }
+
+    fn observe_attempt(&mut self, duration: Duration) {
+        self.inner.observe_attempt(duration);
+    }
+
+    fn observe_error(&mut self, label: &str) {
+        self.inner.observe_error(label);
+    }
+
+    fn observe_time(&mut self, now: Instant) {
+        self.inner.observe_time(now);
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        self.inner.inspect(inspection);
+        if inspection.zero_delay {
+            inspection.warnings.push(PolicyWarning::JitterOnZeroBase);
         }
     }
 }
@@ -222,6 +1451,14 @@ mod tests {
         assert_eq!(bo.next_retry(), Some(Duration::from_secs(5)));
     }
 
+    #[test]
+    fn test_sequence() {
+        let mut bo = sequence(vec![Duration::from_secs(1), Duration::from_secs(5)]);
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(1)));
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(5)));
+        assert_eq!(bo.next_retry(), None);
+    }
+
     #[test]
     fn test_min_backoff() {
         let mut bo = constant(Duration::from_secs(5)).min_backoff(Duration::from_secs(10));
@@ -253,6 +1490,14 @@ mod tests {
         assert_eq!(bo.next_retry(), Some(Duration::from_secs(8)));
     }
 
+    #[test]
+    fn test_exponential_by() {
+        let mut bo = constant(Duration::from_secs(1)).exponential_by(3);
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(1)));
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(3)));
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(9)));
+    }
+
     #[test]
     fn test_jitter() {
         let mut bo = constant(Duration::from_secs(1)).jitter(0.1);
@@ -263,6 +1508,15 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_jitter_seed() {
+        let mut a = constant(Duration::from_secs(1)).jitter(0.5).seed(7);
+        let mut b = constant(Duration::from_secs(1)).jitter(0.5).seed(7);
+        for _i in 0..100 {
+            assert_eq!(a.next_retry(), b.next_retry());
+        }
+    }
+
     #[test]
     fn test_num_attempts() {
         let mut bo = constant(Duration::from_secs(1)).num_attempts(3);
@@ -272,6 +1526,301 @@ mod tests {
         assert_eq!(bo.next_retry(), None);
     }
 
+    #[test]
+    fn test_worst_case_total() {
+        let bo = constant(Duration::from_secs(1))
+            .exponential()
+            .jitter(0.5)
+            .max_backoff(Duration::from_secs(3))
+            .num_attempts(4);
+        assert_eq!(
+            bo.worst_case_total(100),
+            Some(WorstCase {
+                attempts: 4,
+                total_delay: Duration::from_secs(6),
+            })
+        );
+
+        let bo = constant(Duration::from_secs(1)).exponential();
+        assert_eq!(bo.worst_case_total(100), None);
+
+        let bo = constant(Duration::from_secs(1))
+            .deadline(Instant::now() + Duration::from_millis(10_500));
+        let worst = bo.worst_case_total(100).unwrap();
+        assert_eq!(worst.attempts, 11);
+        assert!(worst.total_delay > Duration::from_secs(10));
+        assert!(worst.total_delay <= Duration::from_millis(10_500));
+    }
+
+    #[test]
+    fn test_validate() {
+        let bo = constant(Duration::from_secs(1))
+            .exponential()
+            .num_attempts(5);
+        assert_eq!(bo.validate(), vec![]);
+
+        let bo = constant(Duration::from_secs(1)).exponential();
+        assert_eq!(bo.validate(), vec![PolicyWarning::Unbounded]);
+
+        let bo = instant().deadline(Instant::now());
+        assert_eq!(bo.validate(), vec![PolicyWarning::ZeroDelayUnbounded]);
+
+        let bo = instant().jitter(0.5).num_attempts(3);
+        assert_eq!(bo.validate(), vec![PolicyWarning::JitterOnZeroBase]);
+    }
+
+    #[test]
+    fn test_fail_fast() {
+        let mut bo = constant(Duration::from_secs(1))
+            .fail_fast(Duration::from_millis(100), constant(Duration::from_secs(5)));
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(1)));
+        bo.observe_attempt(Duration::from_millis(10));
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(5)));
+        bo.observe_attempt(Duration::from_secs(30));
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_stop_when() {
+        use std::sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        };
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = stop.clone();
+        let mut bo =
+            constant(Duration::from_secs(1)).stop_when(move || flag.load(Ordering::SeqCst));
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(1)));
+        stop.store(true, Ordering::SeqCst);
+        assert_eq!(bo.next_retry(), None);
+    }
+
+    #[test]
+    fn test_fibonacci() {
+        let mut bo = fibonacci(Duration::from_millis(100));
+        let delays: Vec<_> = (0..7).map(|_| bo.next_retry().unwrap()).collect();
+        let expected: Vec<_> = [1, 1, 2, 3, 5, 8, 13]
+            .iter()
+            .map(|n| Duration::from_millis(100 * n))
+            .collect();
+        assert_eq!(delays, expected);
+
+        let mut bo = fibonacci(Duration::from_secs(1));
+        for _i in 0..100 {
+            bo.next_retry();
+        }
+        assert!(bo.next_retry().is_some());
+    }
+
+    #[test]
+    fn test_random_between() {
+        let mut bo = random_between(Duration::from_secs(1), Duration::from_secs(2));
+        let range = Duration::from_secs(1)..=Duration::from_secs(2);
+        for _i in 0..10_000 {
+            assert!(range.contains(&bo.next_retry().unwrap()));
+        }
+        assert_eq!(bo.max_retry(), Some(Duration::from_secs(2)));
+
+        let mut bo = random_between(Duration::from_secs(1), Duration::from_secs(1));
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_decorrelated_jitter() {
+        let ms = Duration::from_millis;
+        let mut bo = decorrelated_jitter(ms(100), ms(1000)).seed(7);
+        let mut prev = ms(100);
+        for _i in 0..1000 {
+            let delay = bo.next_retry().unwrap();
+            assert!(delay >= ms(100) && delay <= (prev * 3).min(ms(1000)));
+            prev = delay;
+        }
+        assert_eq!(bo.max_retry(), Some(ms(1000)));
+
+        let mut bo = decorrelated_jitter(ms(100), ms(100));
+        assert_eq!(bo.next_retry(), Some(ms(100)));
+    }
+
+    #[test]
+    fn test_quantize() {
+        let mut bo = constant(Duration::from_millis(1250)).quantize(Duration::from_secs(1));
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(2)));
+
+        let mut bo = constant(Duration::from_millis(300)).quantize(Duration::from_millis(100));
+        assert_eq!(bo.next_retry(), Some(Duration::from_millis(300)));
+
+        let mut bo = instant().quantize(Duration::from_millis(100));
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn test_max_retries() {
+        let mut bo = constant(Duration::from_secs(1)).max_retries(2);
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(1)));
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(1)));
+        assert_eq!(bo.next_retry(), None);
+
+        let mut bo = constant(Duration::from_secs(1)).max_retries(0);
+        assert_eq!(bo.next_retry(), None);
+    }
+
+    #[test]
+    fn test_immediate_first_retry() {
+        let mut bo = constant(Duration::from_secs(1))
+            .exponential()
+            .immediate_first_retry()
+            .num_attempts(4);
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(0)));
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(1)));
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(2)));
+        assert_eq!(bo.next_retry(), None);
+    }
+
+    #[test]
+    fn test_weighted() {
+        let mut bo = weighted(vec![
+            (Duration::from_secs(1), 3),
+            (Duration::from_secs(5), 1),
+            (Duration::from_secs(30), 0),
+        ]);
+        let mut ones = 0;
+        for _i in 0..10_000 {
+            match bo.next_retry().unwrap().as_secs() {
+                1 => ones += 1,
+                5 => {}
+                dur => panic!("unexpected delay {}", dur),
+            }
+        }
+        assert!(ones > 7_000 && ones < 8_000);
+        assert_eq!(bo.max_retry(), Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_warmup() {
+        let mut bo = constant(Duration::from_secs(10))
+            .exponential()
+            .warmup(2, Duration::from_millis(100));
+        assert_eq!(bo.next_retry(), Some(Duration::from_millis(100)));
+        assert_eq!(bo.next_retry(), Some(Duration::from_millis(100)));
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(10)));
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(20)));
+    }
+
+    #[test]
+    fn test_splay() {
+        let window = Duration::from_secs(30);
+        let first = |id| {
+            constant(Duration::from_secs(1))
+                .splay(window, id)
+                .next_retry()
+        };
+        let a = first("replica-1").unwrap();
+        assert!(a >= Duration::from_secs(1) && a < Duration::from_secs(31));
+        assert_eq!(first("replica-1"), Some(a));
+        assert_ne!(first("replica-2"), Some(a));
+
+        let mut bo = constant(Duration::from_secs(1)).splay(window, "replica-1");
+        bo.next_retry();
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(1)));
+        let mut bo = instant().splay(Duration::from_secs(0), 7);
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn test_reset_on_error_change() {
+        let mut bo = reset_on_error_change(|| constant(Duration::from_secs(1)).exponential());
+        bo.observe_error("rate_limited");
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(1)));
+        bo.observe_error("rate_limited");
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(2)));
+        bo.observe_error("connection_refused");
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(1)));
+        bo.observe_error("connection_refused");
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(2)));
+
+        // An inner cap starts over with every reset.
+        let mut bo = reset_on_error_change(|| constant(Duration::from_secs(1)).num_attempts(2));
+        assert_eq!(bo.validate(), vec![PolicyWarning::Unbounded]);
+        for label in ["a", "b", "a", "b", "a"] {
+            bo.observe_error(label);
+            assert_eq!(bo.next_retry(), Some(Duration::from_secs(1)));
+        }
+
+        let mut bo = reset_on_error_change(|| constant(Duration::from_secs(1)).exponential())
+            .num_attempts(3);
+        assert_eq!(bo.validate(), vec![]);
+        for label in ["a", "b"] {
+            bo.observe_error(label);
+            assert_eq!(bo.next_retry(), Some(Duration::from_secs(1)));
+        }
+        bo.observe_error("a");
+        assert_eq!(bo.next_retry(), None);
+    }
+
+    #[test]
+    fn test_min_attempts() {
+        let mut bo = constant(Duration::from_secs(1))
+            .deadline(Instant::now())
+            .min_attempts(3, Duration::from_millis(100));
+        assert_eq!(bo.next_retry(), Some(Duration::from_millis(100)));
+        assert_eq!(bo.next_retry(), Some(Duration::from_millis(100)));
+        assert_eq!(bo.next_retry(), None);
+
+        let mut bo = constant(Duration::from_secs(1))
+            .num_attempts(2)
+            .min_attempts(3, Duration::from_millis(100));
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(1)));
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(1)));
+        assert_eq!(bo.next_retry(), None);
+
+        let mut bo = constant(Duration::from_secs(1))
+            .num_attempts(4)
+            .min_attempts(2, Duration::from_millis(100));
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(1)));
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(1)));
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(1)));
+        assert_eq!(bo.next_retry(), None);
+    }
+
+    #[test]
+    fn test_jitter_zero_base() {
+        let mut bo = instant().jitter(0.5);
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(0)));
+        let mut bo = constant(Duration::from_nanos(1)).jitter(0.1);
+        assert_eq!(bo.next_retry(), Some(Duration::from_nanos(1)));
+        let mut bo = constant(Duration::from_micros(10)).jitter(1.0);
+        assert!(bo.next_retry().unwrap() <= Duration::from_micros(10));
+
+        let mut bo = instant().jitter(0.5).min_delay(Duration::from_millis(1));
+        assert_eq!(bo.next_retry(), Some(Duration::from_millis(1)));
+        assert_eq!(bo.max_retry(), Some(Duration::from_millis(1)));
+    }
+
+    #[test]
+    fn test_jitter_max_delay() {
+        let mut bo = constant(Duration::MAX).jitter(1.0);
+        assert!(bo.next_retry().is_some());
+        let mut bo = constant(Duration::MAX).jitter_keyed(1.0, "host-a");
+        assert!(bo.next_retry().is_some());
+    }
+
+    #[test]
+    fn test_jitter_keyed() {
+        let schedule = |key| {
+            let mut bo = constant(Duration::from_secs(1)).jitter_keyed(0.5, key);
+            (0..20)
+                .map(|_| bo.next_retry().unwrap())
+                .collect::<Vec<_>>()
+        };
+        let a = schedule("host-a");
+        assert_eq!(schedule("host-a"), a);
+        assert_ne!(schedule("host-b"), a);
+        let range = Duration::from_millis(500)..=Duration::from_secs(1);
+        assert!(a.iter().all(|dur| range.contains(dur)));
+        assert!(a.windows(2).any(|w| w[0] != w[1]));
+    }
+
     #[test]
     fn deadline() {
         let mut bo =
@@ -0,0 +1,90 @@
+use futures_retrying::{
+    block_on, parse_backoff, retry_command, AttemptContext, CommandError, Control, Middleware,
+};
+use std::{
+    env,
+    process::{self, Command, ExitStatus},
+    sync::{Arc, Mutex},
+};
+
+const USAGE: &str = "usage: retry [--backoff SPEC] -- COMMAND [ARGS...]
+
+SPEC is `instant` or KIND:DELAY[,OPTION...] where KIND is `constant` or
+`exponential` and OPTION is one of `xFACTOR`, `min=DELAY`, `max=DELAY`,
+`jitter=SCALE`, `attempts=N` (total tries) or `retries=N` (tries after the
+first). Delays are written like `250ms`, `1s`, `5m` or `1h`.
+The default is `exponential:1s,max=1m,attempts=10`.";
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let mut spec = String::from("exponential:1s,max=1m,attempts=10");
+    let mut command = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--backoff" => match args.next() {
+                Some(value) => spec = value,
+                None => fail("--backoff requires a value"),
+            },
+            "--" => {
+                command.extend(args.by_ref());
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ => fail(&format!("unexpected argument `{}`", arg)),
+        }
+    }
+    if command.is_empty() {
+        fail("missing command");
+    }
+
+    let backoff = match parse_backoff(&spec) {
+        Ok(backoff) => backoff,
+        Err(err) => fail(&format!("invalid backoff `{}`: {}", spec, err)),
+    };
+
+    let last_exit = Arc::new(Mutex::new(None));
+    let res = block_on(
+        retry_command(
+            || {
+                let mut cmd = Command::new(&command[0]);
+                cmd.args(&command[1..]);
+                cmd
+            },
+            backoff,
+        )
+        .middleware(Report(last_exit.clone())),
+    );
+
+    if res.is_err() {
+        let code = match *last_exit.lock().unwrap() {
+            Some(status) => exit_code(status),
+            None => 1,
+        };
+        process::exit(code);
+    }
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("retry: {}\n\n{}", msg, USAGE);
+    process::exit(2);
+}
+
+fn exit_code(status: ExitStatus) -> i32 {
+    status.code().unwrap_or(1)
+}
+
+/// Reports failed attempts on stderr and remembers the last exit status.
+struct Report(Arc<Mutex<Option<ExitStatus>>>);
+
+impl Middleware<ExitStatus, CommandError> for Report {
+    fn after_failure(&mut self, context: &mut AttemptContext, error: &CommandError) -> Control {
+        let attempt = context.attempt.number;
+        eprintln!("retry: attempt {} failed: {}", attempt, error);
+        if let CommandError::Exit(status) = error {
+            *self.0.lock().unwrap() = Some(*status);
+        }
+        Control::Continue
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{report_error, Backoff, Classifier, ErrorKind, RetryError};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Retry a synchronous task, sleeping the thread between attempts.
+///
+/// For build scripts, CLI tools and setup code running outside of any async runtime. Fails
+/// with `RetryError::Exhausted` holding the last error once `backoff` gives up.
+pub fn retry_blocking<F, T, E, S>(task: F, backoff: S) -> Result<T, RetryError<E>>
+where
+    F: FnMut() -> Result<T, E>,
+    E: std::fmt::Debug,
+    S: Backoff,
+{
+    retry_blocking_with(task, backoff, |_: &E| ErrorKind::Transient)
+}
+
+/// Retry a synchronous task like `retry_blocking`, giving up right away with
+/// `RetryError::Aborted` on errors `classifier` deems permanent.
+pub fn retry_blocking_with<F, T, E, S, C>(
+    mut task: F,
+    mut backoff: S,
+    classifier: C,
+) -> Result<T, RetryError<E>>
+where
+    F: FnMut() -> Result<T, E>,
+    E: std::fmt::Debug,
+    S: Backoff,
+    C: Classifier<E>,
+{
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let started = Instant::now();
+        let err = match task() {
+            Ok(item) => return Ok(item),
+            Err(err) => err,
+        };
+        if classifier.classify(&err) == ErrorKind::Permanent {
+            report_error(&err, None);
+            return Err(RetryError::Aborted {
+                attempts,
+                error: Some(err),
+            });
+        }
+        backoff.observe_attempt(started.elapsed());
+        let retry_after = backoff.next_retry();
+        report_error(&err, retry_after);
+        match retry_after {
+            Some(delay) if delay > Duration::from_secs(0) => thread::sleep(delay),
+            Some(_) => {}
+            None => {
+                return Err(RetryError::Exhausted {
+                    attempts,
+                    error: err,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{constant, instant};
+
+    #[test]
+    fn test_retry_blocking() {
+        let mut calls = 0;
+        let res = retry_blocking(
+            || {
+                calls += 1;
+                if calls < 3 {
+                    Err("busy")
+                } else {
+                    Ok(calls)
+                }
+            },
+            constant(Duration::from_millis(1)).num_attempts(5),
+        );
+        assert_eq!(res, Ok(3));
+
+        let mut calls = 0;
+        let res = retry_blocking(
+            || {
+                calls += 1;
+                Err::<(), _>("busy")
+            },
+            instant().num_attempts(3),
+        );
+        assert_eq!(
+            res,
+            Err(RetryError::Exhausted {
+                attempts: 3,
+                error: "busy"
+            })
+        );
+        assert_eq!(calls, 3);
+
+        let mut calls = 0;
+        let res = retry_blocking_with(
+            || {
+                calls += 1;
+                Err::<(), _>("not found")
+            },
+            instant().num_attempts(3),
+            |_: &&str| ErrorKind::Permanent,
+        );
+        assert_eq!(
+            res,
+            Err(RetryError::Aborted {
+                attempts: 1,
+                error: Some("not found")
+            })
+        );
+        assert_eq!(calls, 1);
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Number of buckets the `ttl` of a `RetryBudget` is split into.
+const BUCKETS: usize = 10;
+
+/// Caps the share of retries among all attempts of many `Retry`s.
+///
+/// Every first attempt deposits into the budget and every retry withdraws from it. Within the
+/// last `ttl` (10s by default) at most `ratio` retries per first attempt are allowed, on top of
+/// a reserve of `min_per_sec` retries per second for low traffic. A `Retry` using the budget
+/// (see `Retry::budget`) gives up instead of retrying once the budget is spent, so a fleet of
+/// clients can't multiply the load on a failing service.
+///
+/// Attempts are counted in ten buckets each spanning a tenth of the `ttl`, so the budget takes
+/// constant memory regardless of the traffic and forgets attempts a whole bucket at a time.
+#[derive(Clone)]
+pub struct RetryBudget {
+    ratio: f64,
+    min_per_sec: u32,
+    ttl: Duration,
+    window: Arc<Mutex<Window>>,
+}
+
+struct Window {
+    start: Instant,
+    /// Index of the current bucket, counted in buckets since `start`.
+    current: u64,
+    deposits: [u64; BUCKETS],
+    withdrawals: [u64; BUCKETS],
+}
+
+impl RetryBudget {
+    pub fn new(ratio: f64, min_per_sec: u32) -> Self {
+        assert!(ratio >= 0.0, "ratio must not be negative");
+        RetryBudget {
+            ratio,
+            min_per_sec,
+            ttl: Duration::from_secs(10),
+            window: Arc::new(Mutex::new(Window::new())),
+        }
+    }
+
+    /// Only count the attempts made within the last `ttl`.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        assert!(ttl > Duration::from_secs(0), "ttl must be larger than zero");
+        self.ttl = ttl;
+        self.window = Arc::new(Mutex::new(Window::new()));
+        self
+    }
+
+    /// Record a first attempt.
+    pub fn deposit(&self) {
+        let mut window = self.window.lock().unwrap();
+        let bucket = window.advance(Instant::now(), self.ttl);
+        window.deposits[bucket] += 1;
+    }
+
+    /// Take one retry from the budget, returns whether the retry is allowed.
+    pub fn try_withdraw(&self) -> bool {
+        let mut window = self.window.lock().unwrap();
+        let bucket = window.advance(Instant::now(), self.ttl);
+        if self.left(&window) < 1.0 {
+            return false;
+        }
+        window.withdrawals[bucket] += 1;
+        true
+    }
+
+    /// The number of retries currently allowed.
+    pub fn remaining(&self) -> u32 {
+        let mut window = self.window.lock().unwrap();
+        window.advance(Instant::now(), self.ttl);
+        self.left(&window).max(0.0) as u32
+    }
+
+    fn left(&self, window: &Window) -> f64 {
+        let reserve = self.min_per_sec as f64 * self.ttl.as_secs_f64();
+        let deposits: u64 = window.deposits.iter().sum();
+        let withdrawals: u64 = window.withdrawals.iter().sum();
+        reserve + self.ratio * deposits as f64 - withdrawals as f64
+    }
+}
+
+impl Window {
+    fn new() -> Self {
+        Window {
+            start: Instant::now(),
+            current: 0,
+            deposits: [0; BUCKETS],
+            withdrawals: [0; BUCKETS],
+        }
+    }
+
+    /// Move to the bucket `now` falls into, clearing the buckets older than `ttl`, and
+    /// return its index.
+    fn advance(&mut self, now: Instant, ttl: Duration) -> usize {
+        let width = (ttl.as_nanos() / BUCKETS as u128).max(1);
+        let index = ((now - self.start).as_nanos() / width) as u64;
+        let stale = index.saturating_sub(self.current).min(BUCKETS as u64);
+        for i in 1..=stale {
+            let bucket = ((self.current + i) % BUCKETS as u64) as usize;
+            self.deposits[bucket] = 0;
+            self.withdrawals[bucket] = 0;
+        }
+        self.current = self.current.max(index);
+        (self.current % BUCKETS as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_budget() {
+        let budget = RetryBudget::new(0.2, 0);
+        assert!(!budget.try_withdraw());
+        for _i in 0..10 {
+            budget.deposit();
+        }
+        assert_eq!(budget.remaining(), 2);
+        let shared = budget.clone();
+        assert!(shared.try_withdraw());
+        assert!(budget.try_withdraw());
+        assert!(!budget.try_withdraw());
+
+        let budget = RetryBudget::new(0.0, 1).ttl(Duration::from_millis(20));
+        assert_eq!(budget.remaining(), 0);
+        let budget = RetryBudget::new(0.0, 100).ttl(Duration::from_millis(20));
+        assert!(budget.try_withdraw() && budget.try_withdraw());
+        assert!(!budget.try_withdraw());
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(budget.try_withdraw());
+    }
+}
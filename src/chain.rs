@@ -0,0 +1,105 @@
+use crate::{BoxRetry, RetryError};
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Try a chain of alternatives, each with its own backoff, until one succeeds.
+///
+/// The retries are run in order: once a retry gives up, the next one is started. This is
+/// useful to fall back to another region, a cheaper provider or a local cache when the
+/// primary keeps failing. Resolves to the first success, or the error of the last
+/// alternative when every alternative gave up.
+///
+/// # Panics
+///
+/// Panics when `alternatives` is empty.
+pub fn retry_chain<T, E, I>(alternatives: I) -> RetryChain<T, E>
+where
+    I: IntoIterator<Item = BoxRetry<T, E>>,
+{
+    let alternatives: VecDeque<_> = alternatives.into_iter().collect();
+    assert!(!alternatives.is_empty(), "alternatives must not be empty");
+    RetryChain {
+        alternatives,
+        current: 0,
+    }
+}
+
+/// Future returned by `retry_chain`
+pub struct RetryChain<T, E> {
+    alternatives: VecDeque<BoxRetry<T, E>>,
+    current: usize,
+}
+
+impl<T, E> RetryChain<T, E> {
+    /// The index of the alternative currently being retried.
+    pub fn current(&self) -> usize {
+        self.current
+    }
+}
+
+impl<T, E> Future for RetryChain<T, E> {
+    type Output = Result<T, RetryError<E>>;
+
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        loop {
+            let alternative = this
+                .alternatives
+                .front_mut()
+                .expect("RetryChain polled after completion");
+            match alternative.as_mut().poll(ctx) {
+                Poll::Ready(Ok(item)) => return Poll::Ready(Ok(item)),
+                Poll::Ready(Err(err)) => {
+                    tracing::info!(alternative = this.current, "alternative gave up");
+                    this.alternatives.pop_front();
+                    if this.alternatives.is_empty() {
+                        return Poll::Ready(Err(err));
+                    }
+                    this.current += 1;
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block_on, instant, retry, Backoff};
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    };
+
+    #[test]
+    fn test_retry_chain() {
+        let primary = Arc::new(AtomicU32::new(0));
+        let calls = primary.clone();
+        let fut = retry_chain(vec![
+            retry(
+                move || {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    async { Err::<&str, ()>(()) }
+                },
+                instant().num_attempts(3),
+            )
+            .boxed(),
+            retry(|| async { Ok::<_, ()>("secondary") }, instant()).boxed(),
+            retry(|| async { Ok::<_, ()>("tertiary") }, instant()).boxed(),
+        ]);
+        assert_eq!(block_on(fut).unwrap(), "secondary");
+        assert_eq!(primary.load(Ordering::SeqCst), 3);
+
+        let fut = retry_chain(vec![retry(
+            || async { Err::<(), _>(()) },
+            instant().num_attempts(2),
+        )
+        .boxed()]);
+        assert_eq!(block_on(fut).unwrap_err().attempts(), 2);
+    }
+}
@@ -0,0 +1,248 @@
+use crate::{clock, Attempt, AttemptContext, ErrorKind, ManualClock, Retryable, Sleeper};
+use pin_project::pin_project;
+use rand::{thread_rng, Rng};
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+/// Wraps a task and injects failures into its attempts.
+///
+/// Meant for tests exercising backoff, classification and exhaustion. Injected failures
+/// return the error made by the `error` function without calling the wrapped task.
+pub struct FailureInjector<R>
+where
+    R: Retryable,
+{
+    task: R,
+    error: Box<dyn Fn() -> R::Error + Send + Sync>,
+    every_nth: Option<u32>,
+    probability: f64,
+    script: Mutex<VecDeque<bool>>,
+    latency: Option<Duration>,
+    clock: clock::Clock,
+    calls: AtomicU32,
+}
+
+impl<R> FailureInjector<R>
+where
+    R: Retryable,
+{
+    /// Wrap `task`, injected failures return the error made by `error`.
+    ///
+    /// Without further configuration no failures are injected.
+    pub fn new<F>(task: R, error: F) -> Self
+    where
+        F: Fn() -> R::Error + Send + Sync + 'static,
+    {
+        FailureInjector {
+            task,
+            error: Box::new(error),
+            every_nth: None,
+            probability: 0.0,
+            script: Mutex::new(VecDeque::new()),
+            latency: None,
+            clock: clock::Clock::System,
+            calls: AtomicU32::new(0),
+        }
+    }
+
+    /// Fail every `n`th call.
+    pub fn every_nth(mut self, n: u32) -> Self {
+        assert!(n > 0, "n must be larger than zero");
+        self.every_nth = Some(n);
+        self
+    }
+
+    /// Fail calls with the given probability.
+    pub fn probability(mut self, probability: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&probability),
+            "probability must be between zero and one"
+        );
+        self.probability = probability;
+        self
+    }
+
+    /// Script the outcome of the next calls, `true` fails the call.
+    ///
+    /// Scripted outcomes take precedence over `every_nth` and `probability`.
+    pub fn script<I>(self, script: I) -> Self
+    where
+        I: IntoIterator<Item = bool>,
+    {
+        self.script.lock().unwrap().extend(script);
+        self
+    }
+
+    /// Delay every call, failed or not, by `latency`.
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = Some(latency);
+        self
+    }
+
+    /// Wait out the `latency` on a manual clock, like the one of a `Simulation`.
+    pub fn manual_clock(mut self, clock: ManualClock) -> Self {
+        self.clock = clock::Clock::Manual(clock);
+        self
+    }
+
+    /// Wait out the `latency` with `sleeper` instead of `futures_timer`.
+    pub fn sleeper<T>(mut self, sleeper: T) -> Self
+    where
+        T: Sleeper + 'static,
+    {
+        self.clock = clock::Clock::Custom(Arc::new(sleeper));
+        self
+    }
+
+    /// The number of calls made so far.
+    pub fn calls(&self) -> u32 {
+        self.calls.load(Ordering::SeqCst)
+    }
+
+    /// Make the future of the next call, failing it or calling the task with `call`.
+    fn inject(&self, call: impl FnOnce() -> R::Future) -> Injected<R::Future, R::Error> {
+        let num = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+        let (task, error) = if self.should_fail(num) {
+            (None, Some((self.error)()))
+        } else {
+            (Some(call()), None)
+        };
+        Injected {
+            delay: self.latency.map(|latency| self.clock.sleep(latency)),
+            task,
+            error,
+        }
+    }
+
+    fn should_fail(&self, call: u32) -> bool {
+        if let Some(fail) = self.script.lock().unwrap().pop_front() {
+            return fail;
+        }
+        if let Some(n) = self.every_nth {
+            if call.is_multiple_of(n) {
+                return true;
+            }
+        }
+        self.probability > 0.0 && thread_rng().gen_bool(self.probability)
+    }
+}
+
+impl<R> Retryable for FailureInjector<R>
+where
+    R: Retryable,
+{
+    type Item = R::Item;
+    type Error = R::Error;
+    type Future = Injected<R::Future, R::Error>;
+
+    fn call(&self) -> Self::Future {
+        self.inject(|| self.task.call())
+    }
+
+    fn call_attempt(&self, attempt: &Attempt) -> Self::Future {
+        self.inject(|| self.task.call_attempt(attempt))
+    }
+
+    fn call_with_context(&self, context: &AttemptContext) -> Self::Future {
+        self.inject(|| self.task.call_with_context(context))
+    }
+
+    fn metric_labels(&self) -> &[(&str, String)] {
+        self.task.metric_labels()
+    }
+
+    fn classify(&self, error: &Self::Error) -> ErrorKind {
+        self.task.classify(error)
+    }
+
+    fn report_error(&self, error: &Self::Error, next_retry: Option<Duration>) {
+        self.task.report_error(error, next_retry)
+    }
+
+    fn report_attempt_error(
+        &self,
+        error: &Self::Error,
+        next_retry: Option<Duration>,
+        attempt: &Attempt,
+    ) {
+        self.task.report_attempt_error(error, next_retry, attempt)
+    }
+
+    fn report_timeout(&self, timeout: Duration, next_retry: Option<Duration>) {
+        self.task.report_timeout(timeout, next_retry)
+    }
+}
+
+/// Future of an attempt made through a `FailureInjector`
+#[pin_project]
+pub struct Injected<F, E> {
+    delay: Option<clock::Sleep>,
+    #[pin]
+    task: Option<F>,
+    error: Option<E>,
+}
+
+impl<F, I, E> Future for Injected<F, E>
+where
+    F: Future<Output = Result<I, E>>,
+{
+    type Output = Result<I, E>;
+
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        if let Some(delay) = this.delay {
+            match Pin::new(delay).poll(ctx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(()) => *this.delay = None,
+            }
+        }
+        if let Some(err) = this.error.take() {
+            return Poll::Ready(Err(err));
+        }
+        this.task
+            .as_pin_mut()
+            .expect("polled after completion")
+            .poll(ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block_on, instant, retry, Backoff};
+
+    #[test]
+    fn test_every_nth() {
+        let task = FailureInjector::new(|| async { Ok::<_, &str>(()) }, || "injected").every_nth(2);
+        assert!(block_on(task.call()).is_ok());
+        assert!(block_on(task.call()).is_err());
+        assert!(block_on(task.call()).is_ok());
+        assert!(block_on(task.call()).is_err());
+    }
+
+    #[test]
+    fn test_script() {
+        let task = FailureInjector::new(|| async { Ok::<_, &str>(()) }, || "injected")
+            .script(vec![true, true, false])
+            .latency(Duration::from_millis(1));
+        let res = block_on(retry(task, instant().num_attempts(5)));
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn test_probability() {
+        let task =
+            FailureInjector::new(|| async { Ok::<_, &str>(()) }, || "injected").probability(1.0);
+        let res = block_on(retry(task, instant().num_attempts(3)));
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,357 @@
+use crate::{Attempt, AttemptContext, ErrorKind, FailureTracker, Retryable};
+use pin_project::{pin_project, pinned_drop};
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+/// Stops calling a struggling dependency after repeated failures.
+///
+/// A breaker is shared by every task wrapped with `wrap`, and counts the consecutive failures
+/// of all of them, including calls dropped before they finished. After `threshold` failures in a row the circuit opens and every call fails
+/// fast with `CircuitError::Open` until `cooldown` has passed. Then the circuit half-opens and
+/// lets a single probe call through: the circuit closes again if it succeeds and opens for
+/// another `cooldown` if it fails.
+///
+/// `CircuitError::Open` is a permanent error, a `Retry` of a wrapped task gives up right away
+/// instead of retrying while the circuit is open.
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    inner: Arc<Breaker>,
+}
+
+/// The state of a `CircuitBreaker`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CircuitState {
+    /// Calls go through.
+    Closed,
+    /// Calls fail fast.
+    Open,
+    /// The next call probes whether the dependency recovered.
+    HalfOpen,
+}
+
+struct Breaker {
+    threshold: u32,
+    cooldown: Duration,
+    circuit: Mutex<Circuit>,
+}
+
+struct Circuit {
+    state: CircuitState,
+    failures: FailureTracker,
+    opened: Instant,
+    probing: bool,
+}
+
+impl CircuitBreaker {
+    /// Create a breaker opening after `threshold` consecutive failures for `cooldown`.
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        assert!(threshold > 0, "threshold must be larger than zero");
+        CircuitBreaker {
+            inner: Arc::new(Breaker {
+                threshold,
+                cooldown,
+                circuit: Mutex::new(Circuit {
+                    state: CircuitState::Closed,
+                    failures: FailureTracker::new(cooldown),
+                    opened: Instant::now(),
+                    probing: false,
+                }),
+            }),
+        }
+    }
+
+    /// Guard the calls of `task` with this breaker.
+    pub fn wrap<R>(&self, task: R) -> Guarded<R>
+    where
+        R: Retryable,
+    {
+        Guarded {
+            task,
+            breaker: self.inner.clone(),
+        }
+    }
+
+    /// The current state of the circuit.
+    pub fn state(&self) -> CircuitState {
+        let circuit = self.inner.circuit.lock().unwrap();
+        match circuit.state {
+            CircuitState::Open if circuit.opened.elapsed() >= self.inner.cooldown => {
+                CircuitState::HalfOpen
+            }
+            state => state,
+        }
+    }
+}
+
+impl Breaker {
+    /// Admit a call, returning whether it's the probe, or `None` if the circuit is open.
+    fn acquire(&self) -> Option<bool> {
+        let mut circuit = self.circuit.lock().unwrap();
+        if circuit.state == CircuitState::Open && circuit.opened.elapsed() >= self.cooldown {
+            circuit.state = CircuitState::HalfOpen;
+        }
+        match circuit.state {
+            CircuitState::Closed => Some(false),
+            CircuitState::HalfOpen if !circuit.probing => {
+                circuit.probing = true;
+                Some(true)
+            }
+            CircuitState::Open | CircuitState::HalfOpen => None,
+        }
+    }
+
+    fn record(&self, success: bool, probe: bool) {
+        let mut circuit = self.circuit.lock().unwrap();
+        if probe {
+            circuit.probing = false;
+        }
+        if success {
+            // Calls started before the circuit opened don't close it, only the probe does.
+            if probe || circuit.state == CircuitState::Closed {
+                circuit.state = CircuitState::Closed;
+                circuit.failures.record_success();
+            }
+            return;
+        }
+        circuit.failures.record_failure();
+        let open = match circuit.state {
+            CircuitState::Closed => circuit.failures.consecutive_failures() >= self.threshold,
+            CircuitState::HalfOpen => probe,
+            CircuitState::Open => false,
+        };
+        if open {
+            circuit.state = CircuitState::Open;
+            circuit.opened = Instant::now();
+        }
+    }
+}
+
+/// Error of a call guarded by a `CircuitBreaker`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CircuitError<E> {
+    /// The circuit was open, the task wasn't called.
+    Open,
+    /// The task failed with `E`.
+    Failed(E),
+}
+
+impl<E> fmt::Display for CircuitError<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CircuitError::Open => f.write_str("circuit breaker is open"),
+            CircuitError::Failed(error) => error.fmt(f),
+        }
+    }
+}
+
+impl<E> std::error::Error for CircuitError<E>
+where
+    E: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CircuitError::Open => None,
+            CircuitError::Failed(error) => Some(error),
+        }
+    }
+}
+
+/// Retryable returned by `CircuitBreaker::wrap`
+pub struct Guarded<R> {
+    task: R,
+    breaker: Arc<Breaker>,
+}
+
+impl<R> Guarded<R>
+where
+    R: Retryable,
+{
+    fn guard(&self, call: impl FnOnce() -> R::Future) -> GuardedCall<R::Future> {
+        let probe = self.breaker.acquire();
+        GuardedCall {
+            task: probe.map(|_| call()),
+            breaker: self.breaker.clone(),
+            probe: probe == Some(true),
+        }
+    }
+}
+
+impl<R> Retryable for Guarded<R>
+where
+    R: Retryable,
+{
+    type Item = R::Item;
+    type Error = CircuitError<R::Error>;
+    type Future = GuardedCall<R::Future>;
+
+    fn call(&self) -> Self::Future {
+        self.guard(|| self.task.call())
+    }
+
+    fn call_attempt(&self, attempt: &Attempt) -> Self::Future {
+        self.guard(|| self.task.call_attempt(attempt))
+    }
+
+    fn call_with_context(&self, context: &AttemptContext) -> Self::Future {
+        self.guard(|| self.task.call_with_context(context))
+    }
+
+    fn metric_labels(&self) -> &[(&str, String)] {
+        self.task.metric_labels()
+    }
+
+    fn classify(&self, error: &Self::Error) -> ErrorKind {
+        match error {
+            CircuitError::Open => ErrorKind::Permanent,
+            CircuitError::Failed(error) => self.task.classify(error),
+        }
+    }
+
+    fn report_error(&self, error: &Self::Error, next_retry: Option<Duration>) {
+        match error {
+            CircuitError::Open => tracing::error!("circuit breaker open, failing fast"),
+            CircuitError::Failed(error) => self.task.report_error(error, next_retry),
+        }
+    }
+
+    fn report_attempt_error(
+        &self,
+        error: &Self::Error,
+        next_retry: Option<Duration>,
+        attempt: &Attempt,
+    ) {
+        match error {
+            CircuitError::Open => self.report_error(error, next_retry),
+            CircuitError::Failed(error) => {
+                self.task.report_attempt_error(error, next_retry, attempt)
+            }
+        }
+    }
+
+    fn report_timeout(&self, timeout: Duration, next_retry: Option<Duration>) {
+        self.task.report_timeout(timeout, next_retry)
+    }
+}
+
+/// Future of a call made through a `CircuitBreaker`
+#[pin_project(PinnedDrop)]
+pub struct GuardedCall<F> {
+    #[pin]
+    task: Option<F>,
+    breaker: Arc<Breaker>,
+    probe: bool,
+}
+
+impl<F, I, E> Future for GuardedCall<F>
+where
+    F: Future<Output = Result<I, E>>,
+{
+    type Output = Result<I, CircuitError<E>>;
+
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        let res = match this.task.as_mut().as_pin_mut() {
+            Some(task) => match task.poll(ctx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(res) => res,
+            },
+            None => return Poll::Ready(Err(CircuitError::Open)),
+        };
+        this.task.set(None);
+        this.breaker.record(res.is_ok(), *this.probe);
+        *this.probe = false;
+        Poll::Ready(res.map_err(CircuitError::Failed))
+    }
+}
+
+#[pinned_drop]
+impl<F> PinnedDrop for GuardedCall<F> {
+    fn drop(self: Pin<&mut Self>) {
+        // A call dropped before it finished, e.g. by a timeout, counts as a failure.
+        let this = self.project();
+        if this.task.is_some() {
+            this.breaker.record(false, *this.probe);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block_on, instant, retry, Backoff, RetryError};
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+    #[test]
+    fn test_circuit_breaker() {
+        let healthy = Arc::new(AtomicBool::new(false));
+        let calls = Arc::new(AtomicU32::new(0));
+        let task = {
+            let (healthy, calls) = (healthy.clone(), calls.clone());
+            move || {
+                calls.fetch_add(1, Ordering::SeqCst);
+                let healthy = healthy.load(Ordering::SeqCst);
+                async move {
+                    if healthy {
+                        Ok(())
+                    } else {
+                        Err("unavailable")
+                    }
+                }
+            }
+        };
+
+        let breaker = CircuitBreaker::new(3, Duration::from_millis(50));
+        let res = block_on(retry(breaker.wrap(task.clone()), instant().num_attempts(5)));
+        assert_eq!(
+            res,
+            Err(RetryError::Aborted {
+                attempts: 4,
+                error: Some(CircuitError::Open)
+            })
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        let other = breaker.wrap(task);
+        assert_eq!(block_on(other.call()), Err(CircuitError::Open));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert_eq!(
+            block_on(other.call()),
+            Err(CircuitError::Failed("unavailable"))
+        );
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        std::thread::sleep(Duration::from_millis(60));
+        healthy.store(true, Ordering::SeqCst);
+        let probe = other.call();
+        assert_eq!(block_on(other.call()), Err(CircuitError::Open));
+        assert_eq!(block_on(probe), Ok(()));
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_dropped_calls_fail() {
+        let breaker = CircuitBreaker::new(2, Duration::from_millis(50));
+        let hung = breaker.wrap(std::future::pending::<Result<(), ()>>);
+        drop(hung.call());
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        drop(hung.call());
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        std::thread::sleep(Duration::from_millis(60));
+        drop(hung.call());
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
+}
@@ -0,0 +1,382 @@
+use std::sync::Arc;
+
+/// Whether an error is worth retrying.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// The error may go away by itself, retry.
+    Transient,
+    /// Retrying won't help, give up.
+    Permanent,
+}
+
+/// Decides which errors of a task are worth retrying.
+///
+/// Install a classifier with `Retry::classifier`, the retry gives up as soon as an attempt
+/// fails with a `Permanent` error.
+pub trait Classifier<E: ?Sized> {
+    fn classify(&self, error: &E) -> ErrorKind;
+
+    /// How severe a transient error is.
+    ///
+    /// The `Retry` multiplies the delay before the next attempt by the weight, like `4.0` for
+    /// explicit throttling. Defaults to `None`, which leaves the delay as is.
+    fn weight(&self, _error: &E) -> Option<f64> {
+        None
+    }
+
+    /// Weigh errors with `f`, see `weight`.
+    fn weighted<F>(self, f: F) -> Weighted<Self, F>
+    where
+        Self: Sized,
+        F: Fn(&E) -> Option<f64>,
+    {
+        Weighted { inner: self, f }
+    }
+
+    /// Errors are transient if either classifier deems them transient.
+    fn or<C>(self, other: C) -> Or<Self, C>
+    where
+        Self: Sized,
+        C: Classifier<E>,
+    {
+        Or {
+            left: self,
+            right: other,
+        }
+    }
+
+    /// Errors are transient only if both classifiers deem them transient.
+    fn and<C>(self, other: C) -> And<Self, C>
+    where
+        Self: Sized,
+        C: Classifier<E>,
+    {
+        And {
+            left: self,
+            right: other,
+        }
+    }
+
+    /// Flip the decision of this classifier.
+    fn not(self) -> Not<Self>
+    where
+        Self: Sized,
+    {
+        Not { inner: self }
+    }
+
+    /// Change the decision of this classifier with `f`.
+    fn map<F>(self, f: F) -> Map<Self, F>
+    where
+        Self: Sized,
+        F: Fn(ErrorKind) -> ErrorKind,
+    {
+        Map { inner: self, f }
+    }
+}
+
+impl<E, F> Classifier<E> for F
+where
+    E: ?Sized,
+    F: Fn(&E) -> ErrorKind,
+{
+    fn classify(&self, error: &E) -> ErrorKind {
+        self(error)
+    }
+}
+
+impl<E, C> Classifier<E> for Arc<C>
+where
+    E: ?Sized,
+    C: Classifier<E> + ?Sized,
+{
+    fn classify(&self, error: &E) -> ErrorKind {
+        (**self).classify(error)
+    }
+
+    fn weight(&self, error: &E) -> Option<f64> {
+        (**self).weight(error)
+    }
+}
+
+pub struct Or<A, B> {
+    left: A,
+    right: B,
+}
+
+impl<E, A, B> Classifier<E> for Or<A, B>
+where
+    E: ?Sized,
+    A: Classifier<E>,
+    B: Classifier<E>,
+{
+    fn classify(&self, error: &E) -> ErrorKind {
+        match self.left.classify(error) {
+            ErrorKind::Transient => ErrorKind::Transient,
+            ErrorKind::Permanent => self.right.classify(error),
+        }
+    }
+
+    fn weight(&self, error: &E) -> Option<f64> {
+        match self.left.classify(error) {
+            ErrorKind::Transient => self.left.weight(error),
+            ErrorKind::Permanent => self.right.weight(error),
+        }
+    }
+}
+
+pub struct And<A, B> {
+    left: A,
+    right: B,
+}
+
+impl<E, A, B> Classifier<E> for And<A, B>
+where
+    E: ?Sized,
+    A: Classifier<E>,
+    B: Classifier<E>,
+{
+    fn classify(&self, error: &E) -> ErrorKind {
+        match self.left.classify(error) {
+            ErrorKind::Transient => self.right.classify(error),
+            ErrorKind::Permanent => ErrorKind::Permanent,
+        }
+    }
+
+    fn weight(&self, error: &E) -> Option<f64> {
+        match self.left.classify(error) {
+            ErrorKind::Transient => self.right.weight(error),
+            ErrorKind::Permanent => self.left.weight(error),
+        }
+    }
+}
+
+pub struct Not<C> {
+    inner: C,
+}
+
+impl<E, C> Classifier<E> for Not<C>
+where
+    E: ?Sized,
+    C: Classifier<E>,
+{
+    fn classify(&self, error: &E) -> ErrorKind {
+        match self.inner.classify(error) {
+            ErrorKind::Transient => ErrorKind::Permanent,
+            ErrorKind::Permanent => ErrorKind::Transient,
+        }
+    }
+
+    fn weight(&self, error: &E) -> Option<f64> {
+        self.inner.weight(error)
+    }
+}
+
+pub struct Map<C, F> {
+    inner: C,
+    f: F,
+}
+
+impl<E, C, F> Classifier<E> for Map<C, F>
+where
+    E: ?Sized,
+    C: Classifier<E>,
+    F: Fn(ErrorKind) -> ErrorKind,
+{
+    fn classify(&self, error: &E) -> ErrorKind {
+        (self.f)(self.inner.classify(error))
+    }
+
+    fn weight(&self, error: &E) -> Option<f64> {
+        self.inner.weight(error)
+    }
+}
+
+pub struct Weighted<C, F> {
+    inner: C,
+    f: F,
+}
+
+impl<E, C, F> Classifier<E> for Weighted<C, F>
+where
+    E: ?Sized,
+    C: Classifier<E>,
+    F: Fn(&E) -> Option<f64>,
+{
+    fn classify(&self, error: &E) -> ErrorKind {
+        self.inner.classify(error)
+    }
+
+    fn weight(&self, error: &E) -> Option<f64> {
+        (self.f)(error)
+    }
+}
+
+/// A set of classifiers built up one by one.
+///
+/// Errors are transient when any classifier added with `retry` deems them transient, unless a
+/// classifier added with `unless` deems them permanent. Errors no classifier deems transient
+/// are permanent.
+pub struct ClassifierSet<E: ?Sized> {
+    retry: Vec<Box<dyn Classifier<E> + Send>>,
+    unless: Vec<Box<dyn Classifier<E> + Send>>,
+}
+
+impl<E: ?Sized> ClassifierSet<E> {
+    pub fn new() -> Self {
+        ClassifierSet {
+            retry: Vec::new(),
+            unless: Vec::new(),
+        }
+    }
+
+    /// Retry errors `classifier` deems transient.
+    pub fn retry<C>(mut self, classifier: C) -> Self
+    where
+        C: Classifier<E> + Send + 'static,
+    {
+        self.retry.push(Box::new(classifier));
+        self
+    }
+
+    /// Never retry errors `classifier` deems permanent.
+    pub fn unless<C>(mut self, classifier: C) -> Self
+    where
+        C: Classifier<E> + Send + 'static,
+    {
+        self.unless.push(Box::new(classifier));
+        self
+    }
+}
+
+impl<E: ?Sized> Default for ClassifierSet<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E: ?Sized> Classifier<E> for ClassifierSet<E> {
+    fn classify(&self, error: &E) -> ErrorKind {
+        let vetoed = self
+            .unless
+            .iter()
+            .any(|c| c.classify(error) == ErrorKind::Permanent);
+        let transient = self
+            .retry
+            .iter()
+            .any(|c| c.classify(error) == ErrorKind::Transient);
+        if transient && !vetoed {
+            ErrorKind::Transient
+        } else {
+            ErrorKind::Permanent
+        }
+    }
+
+    /// The weight of the classifier which decided: the first vetoing `unless` classifier, or
+    /// else the first `retry` classifier deeming the error transient.
+    fn weight(&self, error: &E) -> Option<f64> {
+        let vetoed = self
+            .unless
+            .iter()
+            .find(|c| c.classify(error) == ErrorKind::Permanent);
+        let decided = vetoed.or_else(|| {
+            self.retry
+                .iter()
+                .find(|c| c.classify(error) == ErrorKind::Transient)
+        });
+        decided.and_then(|c| c.weight(error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    fn io_kind(error: &io::Error) -> ErrorKind {
+        match error.kind() {
+            io::ErrorKind::ConnectionReset | io::ErrorKind::TimedOut => ErrorKind::Transient,
+            _ => ErrorKind::Permanent,
+        }
+    }
+
+    fn unavailable(error: &io::Error) -> ErrorKind {
+        if error.to_string().contains("503") {
+            ErrorKind::Transient
+        } else {
+            ErrorKind::Permanent
+        }
+    }
+
+    #[test]
+    fn test_classifier_combinators() {
+        let reset = io::Error::from(io::ErrorKind::ConnectionReset);
+        let status = io::Error::other("503 Service Unavailable");
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+
+        let either = io_kind.or(unavailable);
+        assert_eq!(either.classify(&reset), ErrorKind::Transient);
+        assert_eq!(either.classify(&status), ErrorKind::Transient);
+        assert_eq!(either.classify(&denied), ErrorKind::Permanent);
+
+        let both = io_kind.and(unavailable);
+        assert_eq!(both.classify(&reset), ErrorKind::Permanent);
+
+        assert_eq!(io_kind.not().classify(&denied), ErrorKind::Transient);
+        let never = io_kind.map(|_| ErrorKind::Permanent);
+        assert_eq!(never.classify(&reset), ErrorKind::Permanent);
+    }
+
+    #[test]
+    fn test_classifier_set() {
+        let reset = io::Error::from(io::ErrorKind::ConnectionReset);
+        let status = io::Error::other("503 Service Unavailable");
+
+        let idempotent = ClassifierSet::new().retry(io_kind).retry(unavailable);
+        assert_eq!(idempotent.classify(&reset), ErrorKind::Transient);
+        assert_eq!(idempotent.classify(&status), ErrorKind::Transient);
+
+        let non_idempotent = ClassifierSet::new()
+            .retry(io_kind)
+            .retry(unavailable)
+            .unless(|_: &io::Error| ErrorKind::Permanent);
+        assert_eq!(non_idempotent.classify(&reset), ErrorKind::Permanent);
+
+        assert_eq!(
+            ClassifierSet::<io::Error>::new().classify(&reset),
+            ErrorKind::Permanent
+        );
+    }
+
+    #[test]
+    fn test_classifier_weight() {
+        let reset = io::Error::from(io::ErrorKind::ConnectionReset);
+        let status = io::Error::other("503 Service Unavailable");
+
+        assert_eq!(io_kind.weight(&reset), None);
+        let throttled = unavailable.weighted(|_: &io::Error| Some(4.0));
+        assert_eq!(throttled.weight(&status), Some(4.0));
+        let either = io_kind.or(throttled);
+        assert_eq!(either.weight(&status), Some(4.0));
+        let slow = io_kind.weighted(|_: &io::Error| Some(3.0));
+        let either = slow.or(unavailable.weighted(|_: &io::Error| Some(4.0)));
+        assert_eq!(either.weight(&reset), Some(3.0));
+        assert_eq!(either.weight(&status), Some(4.0));
+        let both = io_kind
+            .weighted(|_: &io::Error| Some(3.0))
+            .and(io_kind.weighted(|_: &io::Error| Some(5.0)));
+        assert_eq!(both.weight(&reset), Some(5.0));
+        let set = ClassifierSet::new()
+            .retry(io_kind)
+            .retry(unavailable.weighted(|_: &io::Error| Some(2.0)));
+        assert_eq!(set.weight(&status), Some(2.0));
+        // The weighted classifier rejected the reset, the unweighted one decided.
+        let set = ClassifierSet::new()
+            .retry(unavailable.weighted(|_: &io::Error| Some(2.0)))
+            .retry(io_kind);
+        assert_eq!(set.weight(&reset), None);
+        assert_eq!(set.weight(&status), Some(2.0));
+        let set = set.unless(unavailable.not().weighted(|_: &io::Error| Some(7.0)));
+        assert_eq!(set.weight(&status), Some(7.0));
+    }
+}
@@ -0,0 +1,317 @@
+use crate::{TimerWheel, WheelSleep};
+use futures_timer::Delay;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, OnceLock},
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+/// A clock which only moves forward when `advance` is called.
+///
+/// A `Retry` driven by a manual clock (see `Retry::manual_clock`) measures attempts, times
+/// out and waits for its backoff according to this clock instead of the system time. This
+/// makes retries follow the clock of game loops, simulations and deterministic replays.
+#[derive(Clone, Debug)]
+pub struct ManualClock {
+    inner: Arc<Mutex<ClockState>>,
+}
+
+#[derive(Debug)]
+struct ClockState {
+    start: Instant,
+    elapsed: Duration,
+    sleepers: Vec<(Duration, Waker)>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        ManualClock {
+            inner: Arc::new(Mutex::new(ClockState {
+                start: Instant::now(),
+                elapsed: Duration::from_secs(0),
+                sleepers: Vec::new(),
+            })),
+        }
+    }
+
+    /// Move the clock forward by `duration`, waking all sleeps that are due.
+    pub fn advance(&self, duration: Duration) {
+        let due: Vec<Waker> = {
+            let mut state = self.inner.lock().unwrap();
+            state.elapsed += duration;
+            let elapsed = state.elapsed;
+            let (due, pending) = state
+                .sleepers
+                .drain(..)
+                .partition(|(deadline, _)| *deadline <= elapsed);
+            state.sleepers = pending;
+            due.into_iter().map(|(_, waker)| waker).collect()
+        };
+        for waker in due {
+            waker.wake();
+        }
+    }
+
+    /// The time the clock has been advanced by since it was created.
+    pub fn elapsed(&self) -> Duration {
+        self.inner.lock().unwrap().elapsed
+    }
+
+    /// Make a future which completes once the clock has been advanced by `duration`.
+    pub fn sleep(&self, duration: Duration) -> ManualSleep {
+        let deadline = self.elapsed() + duration;
+        ManualSleep {
+            clock: self.clone(),
+            deadline,
+        }
+    }
+
+    /// The earliest time a pending sleep is due, relative to the creation of the clock.
+    pub(crate) fn next_deadline(&self) -> Option<Duration> {
+        let state = self.inner.lock().unwrap();
+        state.sleepers.iter().map(|(deadline, _)| *deadline).min()
+    }
+
+    /// The current time of the clock, to set deadlines in its time (see `Backoff::deadline`).
+    pub fn now(&self) -> Instant {
+        let state = self.inner.lock().unwrap();
+        state.start + state.elapsed
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Future returned by `ManualClock::sleep`
+#[derive(Debug)]
+pub struct ManualSleep {
+    clock: ManualClock,
+    deadline: Duration,
+}
+
+impl Future for ManualSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.clock.inner.lock().unwrap();
+        if state.elapsed >= self.deadline {
+            return Poll::Ready(());
+        }
+        let deadline = self.deadline;
+        let registered = state
+            .sleepers
+            .iter()
+            .any(|(d, waker)| *d == deadline && waker.will_wake(ctx.waker()));
+        if !registered {
+            state.sleepers.push((deadline, ctx.waker().clone()));
+        }
+        Poll::Pending
+    }
+}
+
+/// Sleeps for the waits and timeouts of a `Retry`, see `Retry::sleeper`.
+///
+/// Implement it to drive retries with the timer of an async runtime, like
+/// `tokio::time::sleep`, so they follow its time controls in tests. `FuturesTimer` is the
+/// default.
+pub trait Sleeper: Send + Sync {
+    /// Make a future which completes after `duration`.
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+
+    /// The current time of this timer.
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Sleeps using `futures_timer::Delay`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FuturesTimer;
+
+impl Sleeper for FuturesTimer {
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(Delay::new(duration))
+    }
+}
+
+/// The instant all coarse wakeups of the system clock are aligned to.
+static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+/// The clock driving a `Retry`.
+#[derive(Clone)]
+pub(crate) enum Clock {
+    System,
+    Manual(ManualClock),
+    Wheel(TimerWheel),
+    Custom(Arc<dyn Sleeper>),
+}
+
+impl fmt::Debug for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Clock::System => f.write_str("System"),
+            Clock::Manual(clock) => f.debug_tuple("Manual").field(clock).finish(),
+            Clock::Wheel(wheel) => f.debug_tuple("Wheel").field(wheel).finish(),
+            Clock::Custom(_) => f.write_str("Custom"),
+        }
+    }
+}
+
+impl Clock {
+    pub(crate) fn now(&self) -> Instant {
+        match self {
+            Clock::System | Clock::Wheel(_) => Instant::now(),
+            Clock::Manual(clock) => clock.now(),
+            Clock::Custom(sleeper) => sleeper.now(),
+        }
+    }
+
+    pub(crate) fn sleep(&self, duration: Duration) -> Sleep {
+        match self {
+            Clock::System => Sleep::System(Delay::new(duration)),
+            Clock::Manual(clock) => Sleep::Manual(clock.sleep(duration)),
+            Clock::Wheel(wheel) => Sleep::Wheel(wheel.sleep(duration)),
+            Clock::Custom(sleeper) => Sleep::Custom(sleeper.sleep(duration)),
+        }
+    }
+
+    /// Sleep for at least `duration`, waking up on the next multiple of `granularity`.
+    ///
+    /// The buckets are aligned for all retries on the same clock, so sleeps ending in the same
+    /// bucket wake up together.
+    pub(crate) fn sleep_coarse(&self, duration: Duration, granularity: Option<Duration>) -> Sleep {
+        match granularity {
+            Some(granularity) => self.sleep(self.round_up(duration, granularity)),
+            None => self.sleep(duration),
+        }
+    }
+
+    fn round_up(&self, duration: Duration, granularity: Duration) -> Duration {
+        let since = match self {
+            Clock::Manual(clock) => clock.elapsed(),
+            Clock::System | Clock::Wheel(_) | Clock::Custom(_) => {
+                EPOCH.get_or_init(Instant::now).elapsed()
+            }
+        };
+        let bucket = granularity.as_nanos();
+        let wake = (since + duration).as_nanos().div_ceil(bucket) * bucket;
+        Duration::from_nanos((wake - since.as_nanos()) as u64)
+    }
+}
+
+pub(crate) enum Sleep {
+    System(Delay),
+    Manual(ManualSleep),
+    Wheel(WheelSleep),
+    Custom(Pin<Box<dyn Future<Output = ()> + Send>>),
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.get_mut() {
+            Sleep::System(delay) => Pin::new(delay).poll(ctx),
+            Sleep::Manual(sleep) => Pin::new(sleep).poll(ctx),
+            Sleep::Wheel(sleep) => Pin::new(sleep).poll(ctx),
+            Sleep::Custom(sleep) => sleep.as_mut().poll(ctx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block_on, retry, Backoff};
+    use std::{
+        sync::atomic::{AtomicU32, Ordering},
+        thread,
+    };
+
+    #[test]
+    fn test_manual_sleep() {
+        let clock = ManualClock::new();
+        let sleep = clock.sleep(Duration::from_secs(10));
+        let handle = thread::spawn(move || block_on(sleep));
+        thread::sleep(Duration::from_millis(10));
+        assert!(!handle.is_finished());
+        clock.advance(Duration::from_secs(5));
+        thread::sleep(Duration::from_millis(10));
+        assert!(!handle.is_finished());
+        clock.advance(Duration::from_secs(5));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_round_up() {
+        let manual = ManualClock::new();
+        let clock = Clock::Manual(manual.clone());
+        let ms = Duration::from_millis;
+        assert_eq!(clock.round_up(ms(10), ms(50)), ms(50));
+        manual.advance(ms(7));
+        assert_eq!(clock.round_up(ms(10), ms(50)), ms(43));
+        assert_eq!(clock.round_up(ms(43), ms(50)), ms(43));
+        assert_eq!(clock.round_up(ms(60), ms(50)), ms(93));
+    }
+
+    #[test]
+    fn test_sleeper() {
+        struct Counting(Arc<AtomicU32>);
+
+        impl Sleeper for Counting {
+            fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                FuturesTimer.sleep(duration)
+            }
+        }
+
+        let sleeps = Arc::new(AtomicU32::new(0));
+        let res = block_on(
+            retry(
+                || async { Err::<(), _>(()) },
+                crate::constant(Duration::from_millis(1)).num_attempts(3),
+            )
+            .sleeper(Counting(sleeps.clone())),
+        );
+        assert!(res.is_err());
+        assert_eq!(sleeps.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_retry_manual_clock() {
+        let clock = ManualClock::new();
+        let attempts = Arc::new(AtomicU32::new(0));
+        let counter = attempts.clone();
+        let fut = retry(
+            move || {
+                let attempt = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                async move {
+                    if attempt < 3 {
+                        Err(())
+                    } else {
+                        Ok(attempt)
+                    }
+                }
+            },
+            crate::constant(Duration::from_secs(60)).num_attempts(5),
+        )
+        .manual_clock(clock.clone());
+        let handle = thread::spawn(move || block_on(fut));
+
+        for _ in 0..100 {
+            if attempts.load(Ordering::SeqCst) == 3 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(5));
+            clock.advance(Duration::from_secs(30));
+        }
+        assert_eq!(handle.join().unwrap().unwrap(), 3);
+        assert!(clock.elapsed() >= Duration::from_secs(120));
+    }
+}
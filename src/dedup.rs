@@ -0,0 +1,62 @@
+use std::time::{Duration, Instant};
+
+/// Collapses reports of the same error repeating attempt after attempt.
+pub(crate) struct ErrorDedup {
+    interval: Duration,
+    last: Option<String>,
+    repeats: u32,
+    first_seen: Instant,
+    last_summary: Instant,
+}
+
+impl ErrorDedup {
+    pub(crate) fn new(interval: Duration) -> Self {
+        let now = Instant::now();
+        ErrorDedup {
+            interval,
+            last: None,
+            repeats: 0,
+            first_seen: now,
+            last_summary: now,
+        }
+    }
+
+    /// Whether the error identified by `key` should be reported on its own.
+    ///
+    /// Repeats of the last error aren't, they are summarized every `interval` instead.
+    pub(crate) fn observe(&mut self, key: String, now: Instant) -> bool {
+        if self.last.as_ref() == Some(&key) {
+            self.repeats += 1;
+            if now - self.last_summary >= self.interval {
+                self.summarize(now);
+                self.last_summary = now;
+            }
+            return false;
+        }
+        self.flush(now);
+        self.last = Some(key);
+        self.repeats = 0;
+        self.first_seen = now;
+        self.last_summary = now;
+        true
+    }
+
+    /// Summarize the repeats of the last error that haven't been summarized yet.
+    pub(crate) fn flush(&mut self, now: Instant) {
+        if self.repeats > 0 && self.last_summary < now {
+            self.summarize(now);
+        }
+        self.last_summary = now;
+    }
+
+    fn summarize(&self, now: Instant) {
+        if let Some(last) = &self.last {
+            tracing::error!(
+                "error {} repeated {} times over {:?}",
+                last,
+                self.repeats,
+                now - self.first_seen
+            );
+        }
+    }
+}
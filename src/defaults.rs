@@ -0,0 +1,73 @@
+use crate::{constant, retry, Backoff, Retry, Retryable};
+use std::{sync::RwLock, time::Duration};
+
+pub(crate) type MakeBackoff = Box<dyn Fn() -> Box<dyn Backoff> + Send + Sync>;
+
+static DEFAULT_POLICY: RwLock<Option<MakeBackoff>> = RwLock::new(None);
+
+/// Set the backoff used by `retry_default` in this process.
+///
+/// Meant to be called by the application, once at startup, so libraries using
+/// `retry_default` retry the way the application wants.
+pub fn set_default_policy<F, S>(factory: F)
+where
+    F: Fn() -> S + Send + Sync + 'static,
+    S: Backoff + 'static,
+{
+    let factory: MakeBackoff = Box::new(move || Box::new(factory()));
+    *DEFAULT_POLICY.write().unwrap() = Some(factory);
+}
+
+/// Make the backoff set with `set_default_policy`.
+///
+/// Without one the backoff starts at 100ms, doubles up to 10s with 20% jitter and gives up
+/// after 5 attempts.
+pub fn default_policy() -> Box<dyn Backoff> {
+    match &*DEFAULT_POLICY.read().unwrap() {
+        Some(factory) => factory(),
+        None => Box::new(
+            constant(Duration::from_millis(100))
+                .exponential()
+                .max_backoff(Duration::from_secs(10))
+                .jitter(0.2)
+                .num_attempts(5),
+        ),
+    }
+}
+
+/// Retry a future with the backoff configured by the application, see `set_default_policy`.
+pub fn retry_default<R>(task: R) -> Retry<R>
+where
+    R: Retryable,
+{
+    retry(task, default_policy())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block_on, instant};
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    };
+
+    #[test]
+    fn test_default_policy() {
+        let mut bo = default_policy();
+        let delay = bo.next_retry().unwrap();
+        assert!(delay >= Duration::from_millis(80) && delay <= Duration::from_millis(100));
+        let worst = default_policy().worst_case_total(100).unwrap();
+        assert_eq!(worst.attempts, 5);
+
+        set_default_policy(|| instant().num_attempts(2));
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+        let res = block_on(retry_default(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async { Err::<(), _>(()) }
+        }));
+        assert!(res.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
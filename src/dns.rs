@@ -0,0 +1,185 @@
+use crate::{retry, Backoff, ErrorKind};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    future::Future,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+
+/// Resolves host names, implemented on top of the resolver of your choice.
+pub trait Resolver: Send + Sync {
+    type Future: Future<Output = Result<Vec<IpAddr>, ResolveError>> + Send + 'static;
+
+    fn resolve(&self, host: &str) -> Self::Future;
+}
+
+/// Error of a failed lookup.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ResolveError {
+    /// The name doesn't exist, retrying won't help.
+    NxDomain,
+    /// The server failed to answer.
+    ServFail,
+    /// The lookup timed out.
+    Timeout,
+    /// Any other failure, treated as transient.
+    Other(String),
+}
+
+impl ResolveError {
+    /// NXDOMAIN is permanent, everything else is transient.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            ResolveError::NxDomain => ErrorKind::Permanent,
+            _ => ErrorKind::Transient,
+        }
+    }
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResolveError::NxDomain => f.write_str("no such domain"),
+            ResolveError::ServFail => f.write_str("server failure"),
+            ResolveError::Timeout => f.write_str("lookup timed out"),
+            ResolveError::Other(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl Error for ResolveError {}
+
+type MakeBackoff = Box<dyn Fn() -> Box<dyn Backoff> + Send + Sync>;
+
+/// Retries lookups of a `Resolver` with backoff.
+///
+/// NXDOMAIN answers are returned right away, server failures and timeouts are retried. The
+/// last good answer for every host is cached and returned when a lookup gives up on a
+/// transient failure.
+pub struct RetryingResolver<R> {
+    resolver: Arc<R>,
+    backoff: MakeBackoff,
+    cache: Mutex<HashMap<String, Vec<IpAddr>>>,
+}
+
+impl<R> RetryingResolver<R>
+where
+    R: Resolver + 'static,
+{
+    /// Retry lookups of `resolver` with backoffs made by `backoff`.
+    pub fn new<S, F>(resolver: R, backoff: F) -> Self
+    where
+        S: Backoff + 'static,
+        F: Fn() -> S + Send + Sync + 'static,
+    {
+        RetryingResolver {
+            resolver: Arc::new(resolver),
+            backoff: Box::new(move || Box::new(backoff())),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Resolve `host`, falling back to its last good answer when the lookup gives up.
+    pub async fn lookup(&self, host: &str) -> Result<Vec<IpAddr>, ResolveError> {
+        let last_error = Arc::new(Mutex::new(None));
+        let resolver = self.resolver.clone();
+        let host_name = host.to_string();
+        let seen = last_error.clone();
+        let res = retry(
+            move || {
+                let fut = resolver.resolve(&host_name);
+                let seen = seen.clone();
+                async move {
+                    let res = fut.await;
+                    if let Err(err) = &res {
+                        *seen.lock().unwrap() = Some(err.clone());
+                    }
+                    res
+                }
+            },
+            (self.backoff)(),
+        )
+        .classifier(ResolveError::kind)
+        .await;
+
+        match res {
+            Ok(addrs) => {
+                let mut cache = self.cache.lock().unwrap();
+                cache.insert(host.to_string(), addrs.clone());
+                Ok(addrs)
+            }
+            Err(_) => {
+                let err = last_error.lock().unwrap().take();
+                let err = err.unwrap_or_else(|| ResolveError::Other("lookup gave up".into()));
+                if err == ResolveError::NxDomain {
+                    return Err(err);
+                }
+                match self.cache.lock().unwrap().get(host) {
+                    Some(addrs) => {
+                        tracing::warn!(host, error = %err, "using last good answer");
+                        Ok(addrs.clone())
+                    }
+                    None => Err(err),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block_on, instant};
+    use std::{
+        collections::VecDeque,
+        future::{ready, Ready},
+    };
+
+    struct Scripted {
+        answers: Mutex<VecDeque<Result<Vec<IpAddr>, ResolveError>>>,
+        calls: Mutex<u32>,
+    }
+
+    impl Resolver for Scripted {
+        type Future = Ready<Result<Vec<IpAddr>, ResolveError>>;
+
+        fn resolve(&self, _host: &str) -> Self::Future {
+            *self.calls.lock().unwrap() += 1;
+            let answer = self.answers.lock().unwrap().pop_front();
+            ready(answer.unwrap_or(Err(ResolveError::Timeout)))
+        }
+    }
+
+    #[test]
+    fn test_retrying_resolver() {
+        let addr: IpAddr = "192.0.2.1".parse().unwrap();
+        let resolver = Scripted {
+            answers: Mutex::new(
+                vec![
+                    Err(ResolveError::ServFail),
+                    Ok(vec![addr]),
+                    Err(ResolveError::Timeout),
+                    Err(ResolveError::Timeout),
+                    Err(ResolveError::NxDomain),
+                ]
+                .into(),
+            ),
+            calls: Mutex::new(0),
+        };
+        let resolver = RetryingResolver::new(resolver, || instant().num_attempts(2));
+
+        assert_eq!(block_on(resolver.lookup("example.com")), Ok(vec![addr]));
+        assert_eq!(block_on(resolver.lookup("example.com")), Ok(vec![addr]));
+        assert_eq!(
+            block_on(resolver.lookup("example.com")),
+            Err(ResolveError::NxDomain)
+        );
+        assert_eq!(*resolver.resolver.calls.lock().unwrap(), 5);
+        assert_eq!(
+            block_on(resolver.lookup("example.org")),
+            Err(ResolveError::Timeout)
+        );
+    }
+}
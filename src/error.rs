@@ -0,0 +1,162 @@
+use std::{fmt, time::Duration};
+
+/// Error returned by a `Retry` that gave up.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RetryError<E> {
+    /// The backoff gave up, `error` is the error of the last attempt.
+    Exhausted { attempts: u32, error: E },
+    /// The backoff gave up after the last attempt timed out.
+    TimedOut { attempts: u32, timeout: Duration },
+    /// A middleware or classifier stopped the retry. `error` is the error of the last
+    /// attempt, if there was one.
+    Aborted { attempts: u32, error: Option<E> },
+    /// The cancellation future of the retry resolved, see `Retry::with_cancellation`.
+    /// `error` is the error of the last attempt, if there was one.
+    Cancelled { attempts: u32, error: Option<E> },
+}
+
+impl<E> RetryError<E> {
+    /// The number of attempts made before giving up.
+    pub fn attempts(&self) -> u32 {
+        match self {
+            RetryError::Exhausted { attempts, .. }
+            | RetryError::TimedOut { attempts, .. }
+            | RetryError::Aborted { attempts, .. }
+            | RetryError::Cancelled { attempts, .. } => *attempts,
+        }
+    }
+
+    /// The error of the last attempt, if it failed with one.
+    pub fn last_error(&self) -> Option<&E> {
+        match self {
+            RetryError::Exhausted { error, .. } => Some(error),
+            RetryError::Aborted { error, .. } | RetryError::Cancelled { error, .. } => {
+                error.as_ref()
+            }
+            RetryError::TimedOut { .. } => None,
+        }
+    }
+
+    /// Take the error of the last attempt, if it failed with one.
+    pub fn into_last_error(self) -> Option<E> {
+        match self {
+            RetryError::Exhausted { error, .. } => Some(error),
+            RetryError::Aborted { error, .. } | RetryError::Cancelled { error, .. } => error,
+            RetryError::TimedOut { .. } => None,
+        }
+    }
+
+    /// Whether the retry gave up because its backoff was exhausted.
+    pub fn is_exhausted(&self) -> bool {
+        matches!(
+            self,
+            RetryError::Exhausted { .. } | RetryError::TimedOut { .. }
+        )
+    }
+
+    /// Change the error of the last attempt with `f`.
+    pub fn map<F, T>(self, f: F) -> RetryError<T>
+    where
+        F: FnOnce(E) -> T,
+    {
+        match self {
+            RetryError::Exhausted { attempts, error } => RetryError::Exhausted {
+                attempts,
+                error: f(error),
+            },
+            RetryError::TimedOut { attempts, timeout } => {
+                RetryError::TimedOut { attempts, timeout }
+            }
+            RetryError::Aborted { attempts, error } => RetryError::Aborted {
+                attempts,
+                error: error.map(f),
+            },
+            RetryError::Cancelled { attempts, error } => RetryError::Cancelled {
+                attempts,
+                error: error.map(f),
+            },
+        }
+    }
+}
+
+impl<E> fmt::Display for RetryError<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RetryError::Exhausted { attempts, error } => {
+                write!(f, "gave up after {} attempts: {}", attempts, error)
+            }
+            RetryError::TimedOut { attempts, timeout } => write!(
+                f,
+                "gave up after {} attempts: attempt timed out after {:?}",
+                attempts, timeout
+            ),
+            RetryError::Aborted {
+                attempts,
+                error: Some(error),
+            } => write!(f, "aborted after {} attempts: {}", attempts, error),
+            RetryError::Aborted {
+                attempts,
+                error: None,
+            } => write!(f, "aborted after {} attempts", attempts),
+            RetryError::Cancelled {
+                attempts,
+                error: Some(error),
+            } => write!(f, "cancelled after {} attempts: {}", attempts, error),
+            RetryError::Cancelled {
+                attempts,
+                error: None,
+            } => write!(f, "cancelled after {} attempts", attempts),
+        }
+    }
+}
+
+impl<E> std::error::Error for RetryError<E>
+where
+    E: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.last_error().map(|error| error as _)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_error() {
+        let err = RetryError::Exhausted {
+            attempts: 3,
+            error: "refused",
+        };
+        assert_eq!(err.attempts(), 3);
+        assert_eq!(err.last_error(), Some(&"refused"));
+        assert!(err.is_exhausted());
+        assert_eq!(err.to_string(), "gave up after 3 attempts: refused");
+        assert_eq!(err.map(str::len).into_last_error(), Some(7));
+
+        let err = RetryError::<&str>::TimedOut {
+            attempts: 2,
+            timeout: Duration::from_secs(1),
+        };
+        assert_eq!(err.last_error(), None);
+        assert!(err.is_exhausted());
+
+        let err = RetryError::<&str>::Aborted {
+            attempts: 1,
+            error: None,
+        };
+        assert!(!err.is_exhausted());
+        assert_eq!(err.to_string(), "aborted after 1 attempts");
+
+        let err = RetryError::Cancelled {
+            attempts: 2,
+            error: Some("refused"),
+        };
+        assert!(!err.is_exhausted());
+        assert_eq!(err.to_string(), "cancelled after 2 attempts: refused");
+    }
+}
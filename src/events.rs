@@ -0,0 +1,185 @@
+use crate::{AttemptContext, Control, Middleware};
+use std::{
+    fmt::{self, Write},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Something that happened to a `Retry`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RetryEvent {
+    /// An attempt was started.
+    AttemptStarted { attempt: u32 },
+    /// An attempt failed with an error, formatted with `Debug`.
+    AttemptFailed { attempt: u32, error: String },
+    /// An attempt timed out.
+    AttemptTimedOut { attempt: u32, timeout: Duration },
+    /// An attempt succeeded.
+    Succeeded { attempt: u32 },
+}
+
+impl RetryEvent {
+    /// Serialize the event as a single line JSON object.
+    ///
+    /// `name` and `timestamp_ms` (milliseconds since the unix epoch) are included as the
+    /// `retry` and `ts` fields.
+    pub fn to_json(&self, name: Option<&str>, timestamp_ms: u128) -> String {
+        let mut out = String::from("{");
+        write!(out, "\"ts\":{}", timestamp_ms).unwrap();
+        if let Some(name) = name {
+            write!(out, ",\"retry\":{}", JsonStr(name)).unwrap();
+        }
+        match self {
+            RetryEvent::AttemptStarted { attempt } => {
+                write!(
+                    out,
+                    ",\"event\":\"attempt_started\",\"attempt\":{}",
+                    attempt
+                )
+            }
+            RetryEvent::AttemptFailed { attempt, error } => write!(
+                out,
+                ",\"event\":\"attempt_failed\",\"attempt\":{},\"error\":{}",
+                attempt,
+                JsonStr(error)
+            ),
+            RetryEvent::AttemptTimedOut { attempt, timeout } => write!(
+                out,
+                ",\"event\":\"attempt_timed_out\",\"attempt\":{},\"timeout_ms\":{}",
+                attempt,
+                timeout.as_millis()
+            ),
+            RetryEvent::Succeeded { attempt } => {
+                write!(out, ",\"event\":\"succeeded\",\"attempt\":{}", attempt)
+            }
+        }
+        .unwrap();
+        out.push('}');
+        out
+    }
+}
+
+/// Writes a string as a quoted and escaped JSON string.
+struct JsonStr<'a>(&'a str);
+
+impl fmt::Display for JsonStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('"')?;
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                '\t' => f.write_str("\\t")?,
+                c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                c => f.write_char(c)?,
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+/// Middleware handing every `RetryEvent` as a line of JSON to a sink.
+///
+/// For structured logging pipelines without `tracing`. The sink can be a function or wrap
+/// the sending half of a channel.
+pub struct JsonEvents<F> {
+    sink: F,
+    name: Option<String>,
+}
+
+impl<F> JsonEvents<F>
+where
+    F: Fn(String) + Send,
+{
+    pub fn new(sink: F) -> Self {
+        JsonEvents { sink, name: None }
+    }
+
+    /// Include `name` in every event to tell retries apart.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    fn emit(&self, event: RetryEvent) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        (self.sink)(event.to_json(self.name.as_deref(), now.as_millis()));
+    }
+}
+
+impl<T, E, F> Middleware<T, E> for JsonEvents<F>
+where
+    E: fmt::Debug,
+    F: Fn(String) + Send,
+{
+    fn before_attempt(&mut self, context: &mut AttemptContext) -> Control {
+        let attempt = context.attempt.number;
+        self.emit(RetryEvent::AttemptStarted { attempt });
+        Control::Continue
+    }
+
+    fn after_failure(&mut self, context: &mut AttemptContext, error: &E) -> Control {
+        let attempt = context.attempt.number;
+        let error = format!("{:?}", error);
+        self.emit(RetryEvent::AttemptFailed { attempt, error });
+        Control::Continue
+    }
+
+    fn after_timeout(&mut self, context: &mut AttemptContext, timeout: Duration) -> Control {
+        let attempt = context.attempt.number;
+        self.emit(RetryEvent::AttemptTimedOut { attempt, timeout });
+        Control::Continue
+    }
+
+    fn after_success(&mut self, context: &AttemptContext, _item: &T) {
+        let attempt = context.attempt.number;
+        self.emit(RetryEvent::Succeeded { attempt });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block_on, instant, retry, Backoff, FailureInjector};
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_to_json() {
+        let event = RetryEvent::AttemptFailed {
+            attempt: 2,
+            error: "\"quoted\"\n".into(),
+        };
+        assert_eq!(
+            event.to_json(Some("fetch"), 1000),
+            r#"{"ts":1000,"retry":"fetch","event":"attempt_failed","attempt":2,"error":"\"quoted\"\n"}"#
+        );
+        let event = RetryEvent::AttemptTimedOut {
+            attempt: 1,
+            timeout: Duration::from_secs(1),
+        };
+        assert_eq!(
+            event.to_json(None, 5),
+            r#"{"ts":5,"event":"attempt_timed_out","attempt":1,"timeout_ms":1000}"#
+        );
+    }
+
+    #[test]
+    fn test_json_events() {
+        let (tx, rx) = mpsc::channel();
+        let task =
+            FailureInjector::new(|| async { Ok::<_, &str>(()) }, || "boom").script(vec![true]);
+        let res = block_on(
+            retry(task, instant().num_attempts(3))
+                .middleware(JsonEvents::new(move |line| tx.send(line).unwrap())),
+        );
+        assert!(res.is_ok());
+        let lines: Vec<String> = rx.try_iter().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].contains(r#""event":"attempt_started","attempt":1"#));
+        assert!(lines[1].contains(r#""event":"attempt_failed","attempt":1,"error":"\"boom\"""#));
+        assert!(lines[3].contains(r#""event":"succeeded","attempt":2"#));
+    }
+}
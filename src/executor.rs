@@ -0,0 +1,35 @@
+use std::{
+    future::Future,
+    sync::Arc,
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+};
+
+/// Run `fut` to completion on the current thread.
+///
+/// A minimal executor parking the thread while the future is pending, for CLI tools and
+/// tests driving a `Retry` outside of any async runtime.
+pub fn block_on<F: Future>(fut: F) -> F::Output {
+    let mut fut = Box::pin(fut);
+    let waker = thread_waker();
+    let mut ctx = Context::from_waker(&waker);
+    loop {
+        match fut.as_mut().poll(&mut ctx) {
+            Poll::Ready(out) => return out,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+/// A waker unparking the current thread.
+pub(crate) fn thread_waker() -> Waker {
+    Waker::from(Arc::new(ThreadWaker(thread::current())))
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
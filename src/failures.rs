@@ -0,0 +1,125 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Counts the failures of an operation over a sliding window of time.
+///
+/// Tracks the outcomes recorded within the last `window`, their failure rate and the streak
+/// of consecutive failures. Use it to gate behavior on the observed health of a dependency,
+/// like shedding optional work while most attempts fail.
+#[derive(Clone, Debug)]
+pub struct FailureTracker {
+    window: Duration,
+    outcomes: VecDeque<(Instant, bool)>,
+    failures: usize,
+    streak: u32,
+}
+
+impl FailureTracker {
+    pub fn new(window: Duration) -> Self {
+        FailureTracker {
+            window,
+            outcomes: VecDeque::new(),
+            failures: 0,
+            streak: 0,
+        }
+    }
+
+    /// Record a successful attempt, ending the streak of failures.
+    pub fn record_success(&mut self) {
+        self.record(false, Instant::now());
+        self.streak = 0;
+    }
+
+    /// Record a failed attempt.
+    pub fn record_failure(&mut self) {
+        self.record(true, Instant::now());
+        self.streak = self.streak.saturating_add(1);
+    }
+
+    /// The number of failures within the window.
+    pub fn failures(&mut self) -> usize {
+        self.expire(Instant::now());
+        self.failures
+    }
+
+    /// The number of successes within the window.
+    pub fn successes(&mut self) -> usize {
+        self.expire(Instant::now());
+        self.outcomes.len() - self.failures
+    }
+
+    /// The share of outcomes within the window which were failures, zero without any.
+    pub fn failure_rate(&mut self) -> f64 {
+        self.expire(Instant::now());
+        match self.outcomes.len() {
+            0 => 0.0,
+            total => self.failures as f64 / total as f64,
+        }
+    }
+
+    /// The number of failures since the last success, regardless of the window.
+    pub fn consecutive_failures(&self) -> u32 {
+        self.streak
+    }
+
+    /// Forget all recorded outcomes.
+    pub fn reset(&mut self) {
+        self.outcomes.clear();
+        self.failures = 0;
+        self.streak = 0;
+    }
+
+    fn record(&mut self, failed: bool, now: Instant) {
+        self.expire(now);
+        self.outcomes.push_back((now, failed));
+        if failed {
+            self.failures += 1;
+        }
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some(&(at, failed)) = self.outcomes.front() {
+            if now - at < self.window {
+                break;
+            }
+            self.outcomes.pop_front();
+            if failed {
+                self.failures -= 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_failure_tracker() {
+        let mut tracker = FailureTracker::new(Duration::from_millis(50));
+        assert_eq!(tracker.failure_rate(), 0.0);
+        tracker.record_failure();
+        tracker.record_failure();
+        tracker.record_success();
+        tracker.record_failure();
+        assert_eq!(tracker.failures(), 3);
+        assert_eq!(tracker.successes(), 1);
+        assert_eq!(tracker.failure_rate(), 0.75);
+        assert_eq!(tracker.consecutive_failures(), 1);
+
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(tracker.failures(), 0);
+        assert_eq!(tracker.failure_rate(), 0.0);
+        assert_eq!(tracker.consecutive_failures(), 1);
+        tracker.record_failure();
+        assert_eq!(tracker.failure_rate(), 1.0);
+        assert_eq!(tracker.consecutive_failures(), 2);
+
+        tracker.reset();
+        assert_eq!(tracker.failures(), 0);
+        assert_eq!(tracker.consecutive_failures(), 0);
+    }
+}
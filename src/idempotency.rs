@@ -0,0 +1,229 @@
+use crate::{retry, Attempt, AttemptContext, Backoff, ErrorKind, Retry, Retryable};
+use rand::{thread_rng, Rng};
+use std::{fmt, future::Future, sync::Arc, time::Duration};
+
+/// Marker for tasks that are safe to attempt more than once.
+///
+/// Implement this for tasks whose repeated execution has the same effect as a single one, or
+/// wrap a task with `idempotent` to assert it at the call site.
+pub trait Idempotent: Retryable {}
+
+/// Retry an idempotent future until it succeeds.
+///
+/// Unlike `retry` this only accepts tasks marked `Idempotent`, so non-idempotent mutations
+/// can't be retried by accident.
+pub fn retry_idempotent<R, S>(task: R, scheduler: S) -> Retry<R>
+where
+    R: Idempotent,
+    S: Backoff + 'static,
+{
+    retry(task, scheduler)
+}
+
+/// Mark a task as idempotent.
+pub fn idempotent<R>(task: R) -> AssertIdempotent<R>
+where
+    R: Retryable,
+{
+    AssertIdempotent { task }
+}
+
+/// Retryable returned by `idempotent`
+pub struct AssertIdempotent<R> {
+    task: R,
+}
+
+impl<R> Retryable for AssertIdempotent<R>
+where
+    R: Retryable,
+{
+    type Item = R::Item;
+    type Error = R::Error;
+    type Future = R::Future;
+
+    fn call(&self) -> Self::Future {
+        self.task.call()
+    }
+
+    fn call_attempt(&self, attempt: &Attempt) -> Self::Future {
+        self.task.call_attempt(attempt)
+    }
+
+    fn call_with_context(&self, context: &AttemptContext) -> Self::Future {
+        self.task.call_with_context(context)
+    }
+
+    fn metric_labels(&self) -> &[(&str, String)] {
+        self.task.metric_labels()
+    }
+
+    fn classify(&self, error: &Self::Error) -> ErrorKind {
+        self.task.classify(error)
+    }
+
+    fn report_error(&self, error: &Self::Error, next_retry: Option<Duration>) {
+        self.task.report_error(error, next_retry)
+    }
+
+    fn report_attempt_error(
+        &self,
+        error: &Self::Error,
+        next_retry: Option<Duration>,
+        attempt: &Attempt,
+    ) {
+        self.task.report_attempt_error(error, next_retry, attempt)
+    }
+
+    fn report_timeout(&self, timeout: Duration, next_retry: Option<Duration>) {
+        self.task.report_timeout(timeout, next_retry)
+    }
+}
+
+impl<R> Idempotent for AssertIdempotent<R> where R: Retryable {}
+
+/// A key identifying one logical operation across all its attempts.
+///
+/// APIs supporting idempotency keys (usually through an `Idempotency-Key` header) perform an
+/// operation at most once per key, which makes retrying non-idempotent requests safe.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct IdempotencyKey(Arc<str>);
+
+impl IdempotencyKey {
+    /// Generate a new random key formatted as a version 4 UUID.
+    pub fn new() -> Self {
+        let mut bytes: [u8; 16] = thread_rng().gen();
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        IdempotencyKey(
+            format!(
+                "{}-{}-{}-{}-{}",
+                hex[0..4].concat(),
+                hex[4..6].concat(),
+                hex[6..8].concat(),
+                hex[8..10].concat(),
+                hex[10..16].concat()
+            )
+            .into(),
+        )
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for IdempotencyKey {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<String> for IdempotencyKey {
+    fn from(key: String) -> Self {
+        IdempotencyKey(key.into())
+    }
+}
+
+impl From<&str> for IdempotencyKey {
+    fn from(key: &str) -> Self {
+        IdempotencyKey(key.into())
+    }
+}
+
+impl fmt::Display for IdempotencyKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Pass the same freshly generated idempotency key to every attempt of `task`.
+pub fn with_idempotency_key<F, Fut, I, E>(task: F) -> WithIdempotencyKey<F>
+where
+    F: Fn(IdempotencyKey) -> Fut,
+    Fut: Future<Output = Result<I, E>>,
+    E: fmt::Debug,
+{
+    WithIdempotencyKey {
+        key: IdempotencyKey::new(),
+        task,
+    }
+}
+
+/// Retryable returned by `with_idempotency_key`
+pub struct WithIdempotencyKey<F> {
+    key: IdempotencyKey,
+    task: F,
+}
+
+impl<F> WithIdempotencyKey<F> {
+    /// Use `key` instead of a generated key.
+    pub fn key(mut self, key: impl Into<IdempotencyKey>) -> Self {
+        self.key = key.into();
+        self
+    }
+}
+
+impl<F, Fut, I, E> Retryable for WithIdempotencyKey<F>
+where
+    F: Fn(IdempotencyKey) -> Fut,
+    Fut: Future<Output = Result<I, E>>,
+    E: fmt::Debug,
+{
+    type Item = I;
+    type Error = E;
+    type Future = Fut;
+
+    fn call(&self) -> Self::Future {
+        (self.task)(self.key.clone())
+    }
+}
+
+impl<F, Fut, I, E> Idempotent for WithIdempotencyKey<F>
+where
+    F: Fn(IdempotencyKey) -> Fut,
+    Fut: Future<Output = Result<I, E>>,
+    E: fmt::Debug,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block_on, instant};
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_new_key() {
+        let key = IdempotencyKey::new();
+        assert_eq!(key.as_str().len(), 36);
+        assert_eq!(&key.as_str()[14..15], "4");
+        assert_ne!(key, IdempotencyKey::new());
+    }
+
+    #[test]
+    fn test_key_is_reused() {
+        let keys = Arc::new(Mutex::new(Vec::new()));
+        let seen = keys.clone();
+        let res = block_on(retry_idempotent(
+            with_idempotency_key(move |key| {
+                seen.lock().unwrap().push(key);
+                async { Err::<(), _>("failed") }
+            }),
+            instant().num_attempts(3),
+        ));
+        assert!(res.is_err());
+        let keys = keys.lock().unwrap();
+        assert_eq!(keys.len(), 3);
+        assert!(keys.iter().all(|key| *key == keys[0]));
+    }
+
+    #[test]
+    fn test_retry_idempotent() {
+        let res = block_on(retry_idempotent(
+            idempotent(|| async { Ok::<_, ()>(1) }),
+            instant(),
+        ));
+        assert_eq!(res.unwrap(), 1);
+    }
+}
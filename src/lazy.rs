@@ -0,0 +1,102 @@
+use crate::{BoxRetry, Retry, RetryError, Retryable, Singleflight};
+use std::sync::OnceLock;
+
+type MakeRetry<T, E> = Box<dyn Fn() -> BoxRetry<T, E> + Send + Sync>;
+
+/// A value initialized on first use by a retry, like a lazily connected client.
+///
+/// The first call to `get` starts the retry made by the initializer, concurrent callers wait
+/// for the same retry. Once it succeeded the value is stored and `get` returns it right away.
+/// If the retry gives up the callers get its error and the next call starts a new retry.
+pub struct RetryLazy<T, E> {
+    value: OnceLock<T>,
+    flights: Singleflight<(), T, E>,
+    init: MakeRetry<T, E>,
+}
+
+impl<T, E> RetryLazy<T, E>
+where
+    T: Clone,
+    E: Clone,
+{
+    pub fn new<F, R>(init: F) -> Self
+    where
+        F: Fn() -> Retry<R> + Send + Sync + 'static,
+        R: Retryable<Item = T, Error = E>,
+        E: std::fmt::Debug,
+        Retry<R>: Send + 'static,
+    {
+        RetryLazy {
+            value: OnceLock::new(),
+            flights: Singleflight::new(),
+            init: Box::new(move || init().boxed()),
+        }
+    }
+
+    /// Get the value, initializing it if needed.
+    pub async fn get(&self) -> Result<&T, RetryError<E>> {
+        if let Some(value) = self.value.get() {
+            return Ok(value);
+        }
+        let value = self.flights.join((), &self.init).await?;
+        Ok(self.value.get_or_init(|| value))
+    }
+
+    /// The value if it has been initialized already.
+    pub fn try_get(&self) -> Option<&T> {
+        self.value.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block_on, instant, retry, Backoff};
+    use futures_timer::Delay;
+    use std::{
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        },
+        thread,
+        time::Duration,
+    };
+
+    #[test]
+    fn test_retry_lazy() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+        let lazy = Arc::new(RetryLazy::new(move || {
+            let counter = counter.clone();
+            retry(
+                move || {
+                    let call = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                    async move {
+                        Delay::new(Duration::from_millis(20)).await;
+                        if call < 2 {
+                            Err(())
+                        } else {
+                            Ok(format!("connection {}", call))
+                        }
+                    }
+                },
+                instant().num_attempts(3),
+            )
+        }));
+        assert!(lazy.try_get().is_none());
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let lazy = lazy.clone();
+                thread::spawn(move || block_on(lazy.get()).unwrap().clone())
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), "connection 2");
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(block_on(lazy.get()).unwrap(), "connection 2");
+        assert_eq!(lazy.try_get().map(String::as_str), Some("connection 2"));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,17 +1,131 @@
-use futures_timer::Delay;
 use pin_project::pin_project;
 use std::{
     future::Future,
     pin::Pin,
+    sync::{Arc, Mutex},
     task::{Context, Poll},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
+mod annotate;
+pub use annotate::{retry_annotated, Annotated, AnnotatedAttempt, Failure, Failures, Notes};
+
+mod async_backoff;
+pub use async_backoff::{retry_async_backoff, AsyncBackoff, NextRetry};
+
+mod audit;
+pub use audit::{AuditRecord, AuditSink, AuditWrite};
+
 mod backoff;
 pub use backoff::*;
 
-#[derive(Clone, Copy, Debug)]
-pub struct Cancelled;
+mod blocking;
+pub use blocking::{retry_blocking, retry_blocking_with};
+
+mod budget;
+pub use budget::RetryBudget;
+
+mod chain;
+pub use chain::{retry_chain, RetryChain};
+
+mod chaos;
+pub use chaos::*;
+
+mod classify;
+pub use classify::{And, Classifier, ClassifierSet, ErrorKind, Map, Not, Or, Weighted};
+
+mod circuit_breaker;
+pub use circuit_breaker::{CircuitBreaker, CircuitError, CircuitState, Guarded, GuardedCall};
+
+mod clock;
+pub use clock::{FuturesTimer, ManualClock, ManualSleep, Sleeper};
+
+mod dedup;
+
+mod defaults;
+pub use defaults::{default_policy, retry_default, set_default_policy};
+
+mod error;
+pub use error::RetryError;
+
+mod executor;
+// Only exported for the `retry` binary, not part of the API.
+#[doc(hidden)]
+pub use executor::block_on;
+
+mod failures;
+pub use failures::FailureTracker;
+
+mod idempotency;
+pub use idempotency::*;
+
+mod lazy;
+pub use lazy::RetryLazy;
+
+mod middleware;
+pub use middleware::{AttemptContext, Control, Middleware};
+
+mod wheel;
+pub use wheel::{TimerWheel, WheelSleep};
+
+mod policy;
+pub use policy::RetryPolicy;
+
+mod quorum;
+pub use quorum::{retry_quorum, RetryQuorum};
+
+mod race;
+pub use race::{retry_race, RetryRace};
+
+pub mod registry;
+pub mod stats;
+
+mod router;
+pub use router::PolicyRouter;
+
+mod shutdown;
+pub use shutdown::{Shutdown, ShutdownReport, ShutdownRetrier};
+
+mod simulation;
+pub use simulation::{Simulation, SimulationReport};
+
+mod singleflight;
+pub use singleflight::{Coalesced, Singleflight};
+
+mod spec;
+pub use spec::{parse_backoff, ParseError};
+
+mod transport;
+pub use transport::{transport_failure, TransportClassifier, TransportFailure};
+
+mod workflow;
+pub use workflow::{Workflow, WorkflowError};
+
+#[cfg(feature = "dns")]
+mod dns;
+#[cfg(feature = "dns")]
+pub use dns::{ResolveError, Resolver, RetryingResolver};
+
+#[cfg(feature = "json")]
+mod events;
+#[cfg(feature = "json")]
+pub use events::{JsonEvents, RetryEvent};
+
+#[cfg(feature = "process")]
+mod process;
+#[cfg(feature = "process")]
+pub use process::*;
+
+#[cfg(feature = "eyre")]
+mod report;
+
+#[cfg(feature = "tracing-error")]
+mod span_trace;
+#[cfg(feature = "tracing-error")]
+pub use span_trace::{trace_attempts, TraceAttempts, Traced, TracedAttempt};
+
+/// A type erased `Retry`, returned by `Retry::boxed`
+pub type BoxRetry<T, E> = Pin<Box<dyn Future<Output = Result<T, RetryError<E>>> + Send>>;
 
 /// Retry a future until it succeeds.
 pub fn retry<R, S>(task: R, scheduler: S) -> Retry<R>
@@ -22,13 +136,91 @@ where
 {
     Retry {
         retryable: task,
-        scheduler: Box::new(scheduler),
-        state: RetryState::Pending,
+        opts: Options {
+            scheduler: Box::new(scheduler),
+            async_scheduler: None,
+            next_delay: None,
+            state: RetryState::Pending,
+            attempt: 0,
+            attempt_started: Instant::now(),
+            context: AttemptContext {
+                attempt: Attempt {
+                    number: 0,
+                    elapsed: Duration::from_secs(0),
+                    previous_delay: None,
+                },
+                metadata: Default::default(),
+                next_delay: None,
+                clock: clock::Clock::System,
+            },
+            clock: clock::Clock::System,
+            attempt_span: None,
+            error_label: None,
+            classifiers: Vec::new(),
+            error_dedup: None,
+            started: None,
+            watchdog: None,
+            watchdog_fut: None,
+            audit: None,
+            last_error: None,
+            finished: None,
+            span: tracing::Span::none(),
+            parent_span: tracing::Span::current(),
+            link_attempts: false,
+            initial_delay: None,
+            on_wait: None,
+            wait_side: None,
+            cancel: None,
+            budget: None,
+            on_retry: None,
+            on_success: None,
+            on_give_up: None,
+            predictive_abort: false,
+            interval_pacing: false,
+            granularity: None,
+            attempt_timeout: None,
+            timeout_schedule: None,
+            timeout_scheduler: None,
+            middleware: middleware::Chain::new(),
+            registration: None,
+            stats: stats::Tracker::new(),
+            timeout_fut: None,
+            waiting_fut: None,
+        },
         trying_fut: None,
-        waiting_fut: None,
     }
 }
 
+/// Retry a future until it succeeds or fails with an error `predicate` rejects.
+///
+/// Errors for which `predicate` returns false are permanent, the `Retry` gives up on them
+/// right away with `RetryError::Aborted`.
+pub fn retry_if<R, S, P>(task: R, scheduler: S, predicate: P) -> Retry<R>
+where
+    R: Retryable,
+    S: Backoff + 'static,
+    P: Fn(&R::Error) -> bool + Send + 'static,
+{
+    retry(task, scheduler).classifier(move |error: &R::Error| {
+        if predicate(error) {
+            ErrorKind::Transient
+        } else {
+            ErrorKind::Permanent
+        }
+    })
+}
+
+/// The attempt a `Retryable` is called for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Attempt {
+    /// The number of the attempt, starting at one.
+    pub number: u32,
+    /// The time since the first attempt started.
+    pub elapsed: Duration,
+    /// The delay waited before this attempt, if any.
+    pub previous_delay: Option<Duration>,
+}
+
 /// Retryable must be implemented for a task that can be retried any number of times.
 ///
 /// All errors wil be reported with `report_error`. The default implementation will report
@@ -41,91 +233,208 @@ pub trait Retryable {
     /// Setup a new attempt at completing the task.
     fn call(&self) -> Self::Future;
 
+    /// Setup `attempt` at completing the task.
+    ///
+    /// Defaults to `call`. Override it to adjust attempts to their number or timing, like
+    /// adding a retry count header to a request.
+    fn call_attempt(&self, _attempt: &Attempt) -> Self::Future {
+        self.call()
+    }
+
+    /// Setup an attempt with the `AttemptContext` prepared by the middleware of the `Retry`.
+    ///
+    /// Defaults to `call_attempt`. Override it to use the metadata set by middleware, like
+    /// headers to inject.
+    fn call_with_context(&self, context: &AttemptContext) -> Self::Future {
+        self.call_attempt(&context.attempt)
+    }
+
+    /// Labels attached to the metrics recorded for this task.
+    ///
+    /// Read by `Retry::register`, which counts the retry in `stats` under the registered name
+    /// and these labels and lists them in the `registry`. Defaults to no labels. Keep the set
+    /// of values small, every distinct combination becomes its own series.
+    fn metric_labels(&self) -> &[(&str, String)] {
+        &[]
+    }
+
+    /// Whether the task may be retried after failing with `error`.
+    ///
+    /// Defaults to `ErrorKind::Transient`. The `Retry` gives up on permanent errors without
+    /// waiting for the backoff.
+    fn classify(&self, _error: &Self::Error) -> ErrorKind {
+        ErrorKind::Transient
+    }
+
     /// Report the error of the last attempt to complete the task.
     fn report_error(&self, error: &Self::Error, next_retry: Option<Duration>) {
+        report_error(error, next_retry)
+    }
+
+    /// Report the error `attempt` failed with.
+    ///
+    /// Defaults to `report_error`.
+    fn report_attempt_error(
+        &self,
+        error: &Self::Error,
+        next_retry: Option<Duration>,
+        _attempt: &Attempt,
+    ) {
+        self.report_error(error, next_retry)
+    }
+
+    /// Report that the last attempt timed out.
+    fn report_timeout(&self, timeout: Duration, next_retry: Option<Duration>) {
         tracing::error!(
-            "error after retry: {:?} (will retry in {:?})",
-            error,
+            "attempt timed out after {:?} (will retry in {:?})",
+            timeout,
             next_retry
         );
     }
 }
 
-/// Retry is return by `retry`
-#[pin_project]
-pub struct Retry<R>
+/// RetryableRef is a `Retryable` whose attempts may borrow from the task.
+///
+/// Because the futures borrow the task, the task must outlive the retry. Use `retry_ref` to
+/// retry a borrowed task.
+pub trait RetryableRef {
+    type Item;
+    type Error: std::fmt::Debug;
+    type Future<'a>: Future<Output = Result<Self::Item, Self::Error>>
+    where
+        Self: 'a;
+
+    /// Setup a new attempt at completing the task.
+    fn call(&self) -> Self::Future<'_>;
+
+    /// Report the error of the last attempt to complete the task.
+    fn report_error(&self, error: &Self::Error, next_retry: Option<Duration>) {
+        report_error(error, next_retry)
+    }
+}
+
+/// Retry a future borrowing from `task` until it succeeds.
+pub fn retry_ref<R, S>(task: &R, scheduler: S) -> Retry<ByRef<'_, R>>
 where
-    R: Retryable,
-    R::Error: std::fmt::Debug,
+    R: RetryableRef,
+    S: Backoff + 'static,
 {
-    retryable: R,
-    scheduler: Box<dyn Backoff>,
-    state: RetryState,
+    retry(ByRef { task }, scheduler)
+}
 
-    #[pin]
-    trying_fut: Option<R::Future>,
+/// Retryable returned by `retry_ref`
+pub struct ByRef<'a, R> {
+    task: &'a R,
+}
 
-    #[pin]
-    waiting_fut: Option<Delay>,
+impl<'a, R> Retryable for ByRef<'a, R>
+where
+    R: RetryableRef,
+{
+    type Item = R::Item;
+    type Error = R::Error;
+    type Future = R::Future<'a>;
+
+    fn call(&self) -> Self::Future {
+        self.task.call()
+    }
+
+    fn report_error(&self, error: &Self::Error, next_retry: Option<Duration>) {
+        self.task.report_error(error, next_retry)
+    }
 }
 
-enum RetryState {
-    Pending,
-    Trying,
-    Waiting,
+/// RetryableMut is a `Retryable` which may change its state between attempts.
+///
+/// Implemented for `FnMut() -> Fut` closures, so counters, cursors and buffers can be
+/// updated without a `RefCell` or `Mutex`. Use `retry_mut` to retry one.
+pub trait RetryableMut {
+    type Item;
+    type Error: std::fmt::Debug;
+    type Future: Future<Output = Result<Self::Item, Self::Error>>;
+
+    /// Setup a new attempt at completing the task.
+    fn call(&mut self) -> Self::Future;
+
+    /// Report the error of the last attempt to complete the task.
+    fn report_error(&self, error: &Self::Error, next_retry: Option<Duration>) {
+        report_error(error, next_retry)
+    }
 }
 
-impl<R> Future for Retry<R>
+impl<F, Fut, I, E> RetryableMut for F
 where
-    R: Retryable,
-    R::Error: std::fmt::Debug,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<I, E>>,
+    E: std::fmt::Debug,
 {
-    type Output = Result<R::Item, Cancelled>;
+    type Item = I;
+    type Error = E;
+    type Future = Fut;
 
-    fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
-        let mut this = self.project();
-        loop {
-            *this.state = match this.state {
-                RetryState::Pending => {
-                    this.waiting_fut.set(None);
-                    this.trying_fut.set(Some(this.retryable.call()));
-                    RetryState::Trying
-                }
-                RetryState::Trying => {
-                    match this.trying_fut.as_mut().as_pin_mut().unwrap().poll(ctx) {
-                        Poll::Pending => return Poll::Pending,
-                        Poll::Ready(Ok(result)) => return Poll::Ready(Ok(result)),
-                        Poll::Ready(Err(err)) => {
-                            let retry_after = this.scheduler.next_retry();
+    fn call(&mut self) -> Self::Future {
+        self()
+    }
+}
 
-                            // log error
-                            this.retryable.report_error(&err, retry_after);
-
-                            match retry_after {
-                                None => return Poll::Ready(Err(Cancelled)),
-                                Some(retry_after) => {
-                                    this.trying_fut.set(None);
-                                    this.waiting_fut.set(Some(Delay::new(retry_after)));
-                                    RetryState::Waiting
-                                }
-                            }
-                        }
-                    }
-                }
-                RetryState::Waiting => {
-                    match this.waiting_fut.as_mut().as_pin_mut().unwrap().poll(ctx) {
-                        Poll::Pending => return Poll::Pending,
-                        Poll::Ready(_) => RetryState::Pending,
-                    }
-                }
-            };
-        }
+/// Retry a future made by a stateful `task` until it succeeds.
+pub fn retry_mut<R, S>(task: R, scheduler: S) -> Retry<ByMut<R>>
+where
+    R: RetryableMut,
+    S: Backoff + 'static,
+{
+    retry(
+        ByMut {
+            task: Mutex::new(task),
+        },
+        scheduler,
+    )
+}
+
+/// Retryable returned by `retry_mut`
+pub struct ByMut<R> {
+    task: Mutex<R>,
+}
+
+impl<R> Retryable for ByMut<R>
+where
+    R: RetryableMut,
+{
+    type Item = R::Item;
+    type Error = R::Error;
+    type Future = R::Future;
+
+    fn call(&self) -> Self::Future {
+        self.task.lock().unwrap().call()
+    }
+
+    fn report_error(&self, error: &Self::Error, next_retry: Option<Duration>) {
+        self.task.lock().unwrap().report_error(error, next_retry)
     }
 }
 
-impl<F, Fut, I, E> Retryable for F
+/// Retry a future made by `task` for every attempt until it succeeds.
+///
+/// Unlike the `Fn() -> Fut` impl of `Retryable`, `task` is passed the `Attempt` it makes the
+/// future for.
+pub fn retry_with_attempt<F, Fut, I, E, S>(task: F, scheduler: S) -> Retry<WithAttempt<F>>
 where
-    F: Fn() -> Fut,
+    F: Fn(&Attempt) -> Fut,
+    Fut: Future<Output = Result<I, E>>,
+    E: std::fmt::Debug,
+    S: Backoff + 'static,
+{
+    retry(WithAttempt { task }, scheduler)
+}
+
+/// Retryable returned by `retry_with_attempt`
+pub struct WithAttempt<F> {
+    task: F,
+}
+
+impl<F, Fut, I, E> Retryable for WithAttempt<F>
+where
+    F: Fn(&Attempt) -> Fut,
     Fut: Future<Output = Result<I, E>>,
     E: std::fmt::Debug,
 {
@@ -134,14 +443,2251 @@ where
     type Future = Fut;
 
     fn call(&self) -> Self::Future {
-        self()
+        self.call_attempt(&Attempt {
+            number: 1,
+            elapsed: Duration::from_secs(0),
+            previous_delay: None,
+        })
+    }
+
+    fn call_attempt(&self, attempt: &Attempt) -> Self::Future {
+        (self.task)(attempt)
     }
 }
 
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn it_works() {
-        assert_eq!(2 + 2, 4);
+/// Retry an async closure until it succeeds.
+///
+/// Unlike the `Fn() -> Fut` impl of `Retryable` this accepts `async || { .. }` closures
+/// capturing by reference. The closure is borrowed and every attempt is boxed.
+pub fn retry_async_fn<F, I, E, S>(task: &F, scheduler: S) -> Retry<AsyncFnRef<'_, F>>
+where
+    F: std::ops::AsyncFn() -> Result<I, E>,
+    E: std::fmt::Debug,
+    S: Backoff + 'static,
+{
+    retry(AsyncFnRef { task }, scheduler)
+}
+
+/// Retryable returned by `retry_async_fn`
+pub struct AsyncFnRef<'a, F> {
+    task: &'a F,
+}
+
+impl<'a, F, I, E> Retryable for AsyncFnRef<'a, F>
+where
+    F: std::ops::AsyncFn() -> Result<I, E>,
+    E: std::fmt::Debug,
+{
+    type Item = I;
+    type Error = E;
+    type Future = Pin<Box<dyn Future<Output = Result<I, E>> + 'a>>;
+
+    fn call(&self) -> Self::Future {
+        let task = self.task;
+        Box::pin(task())
+    }
+}
+
+type ErrorLabel<E> = Box<dyn Fn(&E) -> &'static str + Send>;
+type WaitFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+type OnWait = Box<dyn Fn(u32, Duration) -> WaitFuture + Send>;
+type NextDelay = Pin<Box<dyn Future<Output = (Box<dyn AsyncBackoff>, Option<Duration>)> + Send>>;
+type Watchdog = Box<dyn FnOnce(u32, Duration) + Send>;
+type OnRetry = Box<dyn FnMut(&Attempt, Duration) + Send>;
+type OnSuccess = Box<dyn FnMut(&Attempt) + Send>;
+type OnGiveUp<E> = Box<dyn FnMut(&Attempt, &RetryError<E>) + Send>;
+
+/// Retry is return by `retry`
+#[pin_project]
+pub struct Retry<R>
+where
+    R: Retryable,
+    R::Error: std::fmt::Debug,
+{
+    retryable: R,
+    opts: Options<R::Item, R::Error>,
+
+    #[pin]
+    trying_fut: Option<R::Future>,
+}
+
+/// Everything about a `Retry` but its task and the future of the current attempt.
+struct Options<T, E> {
+    scheduler: Box<dyn Backoff>,
+    async_scheduler: Option<Box<dyn AsyncBackoff>>,
+    next_delay: Option<NextDelay>,
+    state: RetryState,
+    attempt: u32,
+    attempt_started: Instant,
+    context: AttemptContext,
+    clock: clock::Clock,
+    attempt_span: Option<Box<dyn Fn(u32) -> tracing::Span + Send>>,
+    error_label: Option<ErrorLabel<E>>,
+    classifiers: Vec<Box<dyn Classifier<E> + Send>>,
+    error_dedup: Option<dedup::ErrorDedup>,
+    started: Option<Instant>,
+    watchdog: Option<(Duration, Watchdog)>,
+    watchdog_fut: Option<clock::Sleep>,
+    audit: Option<audit::Audit>,
+    last_error: Option<E>,
+    finished: Option<Result<T, RetryError<E>>>,
+    span: tracing::Span,
+    parent_span: tracing::Span,
+    link_attempts: bool,
+    initial_delay: Option<Duration>,
+    on_wait: Option<OnWait>,
+    wait_side: Option<WaitFuture>,
+    cancel: Option<WaitFuture>,
+    budget: Option<RetryBudget>,
+    on_retry: Option<OnRetry>,
+    on_success: Option<OnSuccess>,
+    on_give_up: Option<OnGiveUp<E>>,
+    predictive_abort: bool,
+    interval_pacing: bool,
+    granularity: Option<Duration>,
+    attempt_timeout: Option<Duration>,
+    timeout_schedule: Option<Box<dyn Backoff>>,
+    timeout_scheduler: Option<Box<dyn Backoff>>,
+    middleware: middleware::Chain<T, E>,
+    registration: Option<registry::Registration>,
+    stats: stats::Tracker,
+
+    timeout_fut: Option<clock::Sleep>,
+    waiting_fut: Option<clock::Sleep>,
+}
+
+impl<R> Retry<R>
+where
+    R: Retryable,
+    R::Error: std::fmt::Debug,
+{
+    /// Create a span for every attempt.
+    ///
+    /// The closure is called with the attempt number (starting at 1) and the returned span
+    /// is entered while the attempt is polled and while its error is reported. Use it to
+    /// attach fields like a request id or tenant to all events emitted for the attempt.
+    ///
+    /// The retry is always polled inside the span that was current when it was created, so
+    /// attempt spans are children of that span, even after the first backoff.
+    pub fn attempt_span<F>(mut self, f: F) -> Self
+    where
+        F: Fn(u32) -> tracing::Span + Send + 'static,
+    {
+        self.opts.attempt_span = Some(Box::new(f));
+        self
+    }
+
+    /// Poll the retry inside `span` instead of the span current when it was created.
+    pub fn parent_span(mut self, span: tracing::Span) -> Self {
+        self.opts.parent_span = span;
+        self
+    }
+
+    /// Link every attempt span to the span of the previous attempt with `follows_from`.
+    pub fn link_attempts(mut self) -> Self {
+        self.opts.link_attempts = true;
+        self
+    }
+
+    /// Wait for `delay` before making the first attempt.
+    pub fn initial_delay(mut self, delay: Duration) -> Self {
+        self.opts.initial_delay = Some(delay);
+        self
+    }
+
+    /// Run a future made by `f` while waiting for the next attempt.
+    ///
+    /// `f` is called with the number of the failed attempt and the delay before the next one.
+    /// The future runs concurrently with the wait and is dropped once the wait is over, use
+    /// it to send keepalives, renew a lease or report that the task is still being retried.
+    pub fn on_wait<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn(u32, Duration) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.opts.on_wait = Some(Box::new(move |attempt, delay| Box::pin(f(attempt, delay))));
+        self
+    }
+
+    /// Call `f` whenever an attempt failed and the next one is scheduled.
+    ///
+    /// `f` is called with the failed attempt and the delay before the next one.
+    pub fn on_retry<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(&Attempt, Duration) + Send + 'static,
+    {
+        self.opts.on_retry = Some(Box::new(f));
+        self
+    }
+
+    /// Call `f` with the attempt that succeeded.
+    ///
+    /// The `elapsed` time of the attempt is the time since the first attempt started until
+    /// the task succeeded.
+    pub fn on_success<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(&Attempt) + Send + 'static,
+    {
+        self.opts.on_success = Some(Box::new(f));
+        self
+    }
+
+    /// Call `f` with the last attempt and the error once the retry gave up.
+    pub fn on_give_up<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(&Attempt, &RetryError<R::Error>) + Send + 'static,
+    {
+        self.opts.on_give_up = Some(Box::new(f));
+        self
+    }
+
+    /// Stop retrying once `cancel` resolves.
+    ///
+    /// The attempt in flight or the wait for the next one is dropped right away and the
+    /// retry resolves with `RetryError::Cancelled`.
+    pub fn with_cancellation<F>(mut self, cancel: F) -> Self
+    where
+        F: Future + Send + 'static,
+    {
+        self.opts.cancel = Some(Box::pin(async move {
+            cancel.await;
+        }));
+        self
+    }
+
+    /// Only retry while `budget` allows it.
+    ///
+    /// The first attempt deposits into the budget and every retry withdraws from it. Once the
+    /// budget is spent the retry gives up with the error of the last attempt.
+    pub fn budget(mut self, budget: RetryBudget) -> Self {
+        self.opts.budget = Some(budget);
+        self
+    }
+
+    /// Give up early when the next attempt can't finish before the deadline of the backoff.
+    ///
+    /// Before waiting, the delay plus the average latency of the attempts so far (see
+    /// `health`) is compared to the time left until the deadline. If it doesn't fit the retry
+    /// gives up right away instead of waiting for an attempt that is likely doomed.
+    pub fn predictive_abort(mut self) -> Self {
+        self.opts.predictive_abort = true;
+        self
+    }
+
+    /// Measure delays from the start of the previous attempt instead of its end.
+    ///
+    /// The time the attempt took is subtracted from the delay, so slow attempts don't stretch
+    /// the period between attempts. Use it to poll at an intended cadence.
+    pub fn interval_pacing(mut self) -> Self {
+        self.opts.interval_pacing = true;
+        self
+    }
+
+    /// Fail attempts that take longer than `timeout`.
+    ///
+    /// A timed out attempt is dropped, reported with `report_timeout` and retried like any
+    /// other failed attempt.
+    pub fn timeout_per_attempt(self, timeout: Duration) -> Self {
+        self.timeout_schedule(timeout)
+    }
+
+    /// Fail attempts that take longer than the timeouts produced by `schedule`.
+    ///
+    /// The schedule is advanced once per attempt, so `sequence` can give early attempts less
+    /// time than later ones. Once the schedule returns `None` the last timeout is kept.
+    pub fn timeout_schedule<S>(mut self, schedule: S) -> Self
+    where
+        S: Backoff + 'static,
+    {
+        self.opts.timeout_schedule = Some(Box::new(schedule));
+        self
+    }
+
+    /// Use a separate backoff for attempts that timed out.
+    ///
+    /// Without it timed out attempts use the same backoff as failed attempts.
+    pub fn timeout_backoff<S>(mut self, scheduler: S) -> Self
+    where
+        S: Backoff + 'static,
+    {
+        self.opts.timeout_scheduler = Some(Box::new(scheduler));
+        self
+    }
+
+    /// Ask `backoff` for the delay after every failed attempt instead of the backoff passed
+    /// to `retry`.
+    ///
+    /// The retry awaits `AsyncBackoff::next_delay` before waiting for the next attempt.
+    /// Timed out attempts still use the `timeout_backoff` if there is one.
+    pub fn async_backoff<B>(mut self, backoff: B) -> Self
+    where
+        B: AsyncBackoff + 'static,
+    {
+        self.opts.async_scheduler = Some(Box::new(backoff));
+        self
+    }
+
+    /// Add a middleware intercepting every attempt.
+    pub fn middleware<M>(mut self, middleware: M) -> Self
+    where
+        M: Middleware<R::Item, R::Error> + 'static,
+    {
+        self.opts.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// Label the errors of failed attempts for the backoff.
+    ///
+    /// The label of every error is passed to `Backoff::observe_error`, letting backoffs like
+    /// `reset_on_error_change` react to the kind of failure.
+    pub fn label_errors<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&R::Error) -> &'static str + Send + 'static,
+    {
+        self.opts.error_label = Some(Box::new(f));
+        self
+    }
+
+    /// Call `f` once when the retry has been going for longer than `threshold`.
+    ///
+    /// `f` is called with the number of attempts made so far and the time since the retry was
+    /// first polled, as an early warning long before the backoff gives up. The watchdog has a
+    /// timer of its own, so it fires on time even while an attempt or a wait is in progress.
+    pub fn watchdog<F>(mut self, threshold: Duration, f: F) -> Self
+    where
+        F: FnOnce(u32, Duration) + Send + 'static,
+    {
+        self.opts.watchdog = Some((threshold, Box::new(f)));
+        self
+    }
+
+    /// Emit a tracing event at `level` when the retry has been going for longer than
+    /// `threshold`, see `watchdog`.
+    pub fn watchdog_event(self, threshold: Duration, level: tracing::Level) -> Self {
+        self.watchdog(threshold, move |attempts, elapsed| {
+            macro_rules! alert {
+                ($level:expr) => {
+                    tracing::event!(
+                        $level,
+                        attempts,
+                        "still retrying after {:?} ({} attempts)",
+                        elapsed,
+                        attempts
+                    )
+                };
+            }
+            match level {
+                tracing::Level::ERROR => alert!(tracing::Level::ERROR),
+                tracing::Level::WARN => alert!(tracing::Level::WARN),
+                tracing::Level::INFO => alert!(tracing::Level::INFO),
+                tracing::Level::DEBUG => alert!(tracing::Level::DEBUG),
+                tracing::Level::TRACE => alert!(tracing::Level::TRACE),
+            }
+        })
+    }
+
+    /// Write every decision of this retry to `sink`, see `AuditSink`.
+    pub fn audit<S>(mut self, sink: S) -> Self
+    where
+        S: AuditSink + 'static,
+    {
+        self.opts.audit = Some(audit::Audit::new(Box::new(sink)));
+        self
+    }
+
+    /// Collapse reports of an error repeating attempt after attempt.
+    ///
+    /// Only the first of consecutive equal errors is passed to `Retryable::report_error`,
+    /// the repeats are summarized like "error X repeated 47 times over 12m" every `interval`
+    /// and once the error changes or the retry finishes. Errors are compared by their label
+    /// (see `label_errors`), or by their `Debug` output without one.
+    pub fn dedup_errors(mut self, interval: Duration) -> Self {
+        self.opts.error_dedup = Some(dedup::ErrorDedup::new(interval));
+        self
+    }
+
+    /// Give up as soon as an attempt fails with an error `classifier` deems permanent.
+    ///
+    /// The delay after a transient error is multiplied by its `Classifier::weight`. With
+    /// several classifiers the weights are multiplied as well.
+    pub fn classifier<C>(mut self, classifier: C) -> Self
+    where
+        C: Classifier<R::Error> + Send + 'static,
+    {
+        self.opts.classifiers.push(Box::new(classifier));
+        self
+    }
+
+    /// Drive this retry with a manual clock.
+    ///
+    /// Attempt durations, timeouts and backoff delays follow `clock`, waits only complete
+    /// once the clock has been advanced far enough.
+    pub fn manual_clock(mut self, clock: ManualClock) -> Self {
+        self.opts.clock = clock::Clock::Manual(clock);
+        self
+    }
+
+    /// Sleep with `sleeper` instead of `futures_timer`.
+    ///
+    /// Attempt durations, timeouts and backoff delays follow the clock of `sleeper`.
+    pub fn sleeper<T>(mut self, sleeper: T) -> Self
+    where
+        T: Sleeper + 'static,
+    {
+        self.opts.clock = clock::Clock::Custom(Arc::new(sleeper));
+        self
+    }
+
+    /// Round every wakeup up to the next multiple of `granularity`.
+    ///
+    /// Waits and attempt timeouts end on bucket boundaries shared by all retries, so
+    /// services running many retries wake up far less often at the cost of precision.
+    pub fn timer_granularity(mut self, granularity: Duration) -> Self {
+        assert!(
+            granularity > Duration::from_secs(0),
+            "granularity must be larger than zero"
+        );
+        self.opts.granularity = Some(granularity);
+        self
+    }
+
+    /// Register the waits of this retry with a shared timer wheel.
+    ///
+    /// Saves a timer per retry when running many of them concurrently, at the cost of
+    /// rounding waits up to the tick of the wheel.
+    pub fn timer_wheel(mut self, wheel: TimerWheel) -> Self {
+        self.opts.clock = clock::Clock::Wheel(wheel);
+        self
+    }
+
+    /// Erase the type of the task by boxing the retry.
+    ///
+    /// Useful to store differently typed retries in one collection.
+    pub fn boxed(self) -> BoxRetry<R::Item, R::Error>
+    where
+        Self: Send + 'static,
+    {
+        Box::pin(self)
+    }
+
+    /// Box the future of every attempt.
+    ///
+    /// The future of the current attempt is stored inline, so a large attempt future makes
+    /// the `Retry` and everything holding it just as large. Boxing the attempts keeps the
+    /// `Retry` small for embedding in structs and select arms.
+    pub fn boxed_attempts(self) -> Retry<BoxedAttempts<R>> {
+        Retry {
+            retryable: BoxedAttempts(self.retryable),
+            opts: self.opts,
+            trying_fut: self.trying_fut.map(Box::pin),
+        }
+    }
+
+    /// Moving averages of the latency and success rate of the attempts made so far.
+    ///
+    /// The averages of all retries registered under the same name are available from
+    /// `stats::health`.
+    pub fn health(&self) -> stats::Health {
+        self.opts.stats.health()
+    }
+
+    /// Count this retry in the totals of `stats::snapshot()`, from when it is first polled.
+    ///
+    /// Registered retries (see `register`) are counted as well.
+    pub fn track_stats(mut self) -> Self {
+        self.opts.stats.enable();
+        self
+    }
+
+    /// Register this retry under `name` in the global `registry`.
+    ///
+    /// Registered retries show up in `registry::snapshot()` until they are dropped and are
+    /// counted in `stats::snapshot()` per name and `Retryable::metric_labels` of the task
+    /// (see `stats::series`).
+    pub fn register(mut self, name: impl Into<String>) -> Self {
+        let name = name.into();
+        let labels = self.retryable.metric_labels();
+        self.opts.stats.set_name(stats::series(&name, labels));
+        let labels = labels
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect();
+        self.opts.registration = Some(registry::Registration::new(name, labels));
+        self
+    }
+}
+
+enum RetryState {
+    Pending,
+    Trying,
+    Waiting,
+    /// Waiting for the deadline of the backoff, which passes before the next attempt is due.
+    Expiring,
+    /// Waiting for the `AsyncBackoff` to decide the delay after a failed attempt.
+    Deciding,
+    /// The backoff decided the delay after a failed attempt, `None` to give up.
+    Decided {
+        retry_after: Option<Duration>,
+        deadline: Option<Instant>,
+    },
+}
+
+impl<R> Future for Retry<R>
+where
+    R: Retryable,
+    R::Error: std::fmt::Debug,
+{
+    type Output = Result<R::Item, RetryError<R::Error>>;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.as_mut().project();
+        let opts = this.opts;
+        if let Some(audit) = &mut opts.audit {
+            // Hold on to the result until the audit trail has been written.
+            let flushed = audit.poll_flush(ctx).is_ready();
+            match opts.finished.take() {
+                Some(res) if flushed => return Poll::Ready(res),
+                Some(res) => {
+                    opts.finished = Some(res);
+                    return Poll::Pending;
+                }
+                None => {}
+            }
+        }
+        let clock = &opts.clock;
+        if opts.started.is_none() {
+            opts.watchdog_fut = opts
+                .watchdog
+                .as_ref()
+                .map(|(threshold, _)| clock.sleep(*threshold));
+        }
+        let started = *opts.started.get_or_insert_with(|| clock.now());
+        if let Some(registration) = &mut opts.registration {
+            registration.poll_started();
+        }
+        let attempt = opts.attempt;
+        let was_waiting = matches!(opts.state, RetryState::Waiting | RetryState::Expiring);
+
+        let res = self.as_mut().poll_retry(ctx);
+        let this = self.project();
+        let opts = this.opts;
+        if let Poll::Ready(res) = &res {
+            let info = Attempt {
+                elapsed: opts.clock.now() - started,
+                ..opts.context.attempt
+            };
+            match (res, &mut opts.on_success, &mut opts.on_give_up) {
+                (Ok(_), Some(f), _) => f(&info),
+                (Err(err), _, Some(f)) => f(&info, err),
+                _ => {}
+            }
+        }
+        if let (Poll::Pending, Some(timer)) = (&res, &mut opts.watchdog_fut) {
+            if Pin::new(timer).poll(ctx).is_ready() {
+                opts.watchdog_fut = None;
+                let (_, fire) = opts.watchdog.take().unwrap();
+                fire(opts.attempt, opts.clock.now() - started);
+            }
+        }
+        match (&res, &mut opts.registration) {
+            (Poll::Pending, Some(registration)) => {
+                registration.poll_pending();
+                if was_waiting && opts.attempt == attempt {
+                    registration.spurious_wakeup();
+                }
+            }
+            (Poll::Ready(_), _) => {
+                if let Some(dedup) = &mut opts.error_dedup {
+                    dedup.flush(opts.clock.now());
+                }
+            }
+            _ => {}
+        }
+        if let Some(audit) = &mut opts.audit {
+            if res.is_ready() && audit.poll_flush(ctx).is_pending() {
+                if let Poll::Ready(res) = res {
+                    opts.finished = Some(res);
+                }
+                return Poll::Pending;
+            }
+        }
+        res
+    }
+}
+
+impl<R> Retry<R>
+where
+    R: Retryable,
+    R::Error: std::fmt::Debug,
+{
+    fn poll_retry(
+        self: Pin<&mut Self>,
+        ctx: &mut Context<'_>,
+    ) -> Poll<Result<R::Item, RetryError<R::Error>>> {
+        let mut this = self.project();
+        let opts = this.opts;
+        let _parent = opts.parent_span.enter();
+        opts.stats.polled();
+        if let Some(cancel) = &mut opts.cancel {
+            if cancel.as_mut().poll(ctx).is_ready() {
+                this.trying_fut.set(None);
+                opts.timeout_fut = None;
+                opts.waiting_fut = None;
+                opts.wait_side = None;
+                opts.next_delay = None;
+                audit(&mut opts.audit, opts.attempt, |attempt| {
+                    AuditRecord::Aborted {
+                        attempt,
+                        reason: "cancelled",
+                    }
+                });
+                opts.stats.cancelled();
+                return Poll::Ready(Err(RetryError::Cancelled {
+                    attempts: opts.attempt,
+                    error: opts.last_error.take(),
+                }));
+            }
+        }
+        loop {
+            opts.state = match opts.state {
+                RetryState::Pending if opts.initial_delay.is_some() => {
+                    let delay = opts.initial_delay.take().unwrap();
+                    opts.context.attempt.previous_delay = Some(delay);
+                    opts.waiting_fut = Some(opts.clock.sleep_coarse(delay, opts.granularity));
+                    if let Some(registration) = &mut opts.registration {
+                        registration.update(
+                            0,
+                            registry::RetryStatus::Waiting,
+                            Some(opts.clock.now() + delay),
+                        );
+                    }
+                    RetryState::Waiting
+                }
+                RetryState::Pending => {
+                    if let Some(audit) = &mut opts.audit {
+                        if audit.poll_flush(ctx).is_pending() {
+                            return Poll::Pending;
+                        }
+                    }
+                    opts.attempt += 1;
+                    let span = match &opts.attempt_span {
+                        Some(f) => f(opts.attempt),
+                        None => tracing::Span::none(),
+                    };
+                    if opts.link_attempts {
+                        span.follows_from(&opts.span);
+                    }
+                    opts.span = span;
+                    let _enter = opts.span.enter();
+                    let now = opts.clock.now();
+                    opts.context.attempt.number = opts.attempt;
+                    opts.context.attempt.elapsed = opts
+                        .started
+                        .map_or(Duration::from_secs(0), |started| now - started);
+                    if opts.middleware.before_attempt(&mut opts.context) == Control::Abort {
+                        audit(&mut opts.audit, opts.attempt, |attempt| {
+                            AuditRecord::Aborted {
+                                attempt,
+                                reason: "middleware",
+                            }
+                        });
+                        opts.stats.cancelled();
+                        return Poll::Ready(Err(RetryError::Aborted {
+                            attempts: opts.attempt - 1,
+                            error: opts.last_error.take(),
+                        }));
+                    }
+                    audit(&mut opts.audit, opts.attempt, |attempt| {
+                        AuditRecord::AttemptStarted { attempt }
+                    });
+                    if let (Some(budget), 1) = (&opts.budget, opts.attempt) {
+                        budget.deposit();
+                    }
+                    opts.stats.attempt();
+                    opts.attempt_started = opts.clock.now();
+                    opts.waiting_fut = None;
+                    if let Some(schedule) = &mut opts.timeout_schedule {
+                        if let Some(timeout) = schedule.next_retry() {
+                            opts.attempt_timeout = Some(timeout);
+                        }
+                    }
+                    let (clock, granularity) = (&opts.clock, opts.granularity);
+                    opts.timeout_fut = opts
+                        .attempt_timeout
+                        .map(|timeout| clock.sleep_coarse(timeout, granularity));
+                    opts.context.clock = opts.clock.clone();
+                    this.trying_fut
+                        .set(Some(this.retryable.call_with_context(&opts.context)));
+                    if let Some(registration) = &mut opts.registration {
+                        registration.update(opts.attempt, registry::RetryStatus::Trying, None);
+                    }
+                    RetryState::Trying
+                }
+                RetryState::Trying => {
+                    let _enter = opts.span.enter();
+                    let elapsed = opts.clock.now() - opts.attempt_started;
+                    let attempt = this.trying_fut.as_mut().as_pin_mut().unwrap().poll(ctx);
+                    match attempt {
+                        Poll::Ready(Ok(result)) => {
+                            opts.stats.attempt_finished(elapsed, true);
+                            opts.middleware.after_success(&opts.context, &result);
+                            audit(&mut opts.audit, opts.attempt, |attempt| {
+                                AuditRecord::Succeeded { attempt }
+                            });
+                            opts.stats.success();
+                            return Poll::Ready(Ok(result));
+                        }
+                        Poll::Ready(Err(err)) => {
+                            opts.stats.attempt_finished(elapsed, false);
+                            audit(&mut opts.audit, opts.attempt, |attempt| {
+                                AuditRecord::AttemptFailed {
+                                    attempt,
+                                    error: format!("{:?}", err),
+                                }
+                            });
+                            let permanent = this.retryable.classify(&err) == ErrorKind::Permanent
+                                || opts.classifiers.iter().any(|classifier| {
+                                    classifier.classify(&err) == ErrorKind::Permanent
+                                });
+                            let control = opts.middleware.after_failure(&mut opts.context, &err);
+                            if permanent || control == Control::Abort {
+                                let reason = if permanent {
+                                    "permanent error"
+                                } else {
+                                    "middleware"
+                                };
+                                audit(&mut opts.audit, opts.attempt, |attempt| {
+                                    AuditRecord::Aborted { attempt, reason }
+                                });
+                                this.retryable.report_attempt_error(
+                                    &err,
+                                    None,
+                                    &opts.context.attempt,
+                                );
+                                opts.stats.cancelled();
+                                return Poll::Ready(Err(RetryError::Aborted {
+                                    attempts: opts.attempt,
+                                    error: Some(err),
+                                }));
+                            }
+                            if let Some(label) = &opts.error_label {
+                                opts.scheduler.observe_error(label(&err));
+                            }
+                            opts.last_error = Some(err);
+                            match opts.async_scheduler.take() {
+                                Some(scheduler) => {
+                                    opts.next_delay =
+                                        Some(next_delay(scheduler, elapsed, opts.clock.now()));
+                                    RetryState::Deciding
+                                }
+                                None => {
+                                    opts.scheduler.observe_attempt(elapsed);
+                                    opts.scheduler.observe_time(opts.clock.now());
+                                    RetryState::Decided {
+                                        retry_after: opts.scheduler.next_retry(),
+                                        deadline: deadline_of(&*opts.scheduler),
+                                    }
+                                }
+                            }
+                        }
+                        Poll::Pending => {
+                            let timed_out = match opts.timeout_fut.as_mut() {
+                                Some(timeout) => Pin::new(timeout).poll(ctx).is_ready(),
+                                None => false,
+                            };
+                            if !timed_out {
+                                return Poll::Pending;
+                            }
+                            let timeout = opts.attempt_timeout.unwrap();
+                            opts.stats.attempt_finished(elapsed, false);
+                            audit(&mut opts.audit, opts.attempt, |attempt| {
+                                AuditRecord::AttemptTimedOut { attempt, timeout }
+                            });
+                            let control = opts.middleware.after_timeout(&mut opts.context, timeout);
+                            if control == Control::Abort {
+                                audit(&mut opts.audit, opts.attempt, |attempt| {
+                                    AuditRecord::Aborted {
+                                        attempt,
+                                        reason: "middleware",
+                                    }
+                                });
+                                this.retryable.report_timeout(timeout, None);
+                                opts.stats.cancelled();
+                                return Poll::Ready(Err(RetryError::Aborted {
+                                    attempts: opts.attempt,
+                                    error: None,
+                                }));
+                            }
+                            opts.last_error = None;
+                            match (&mut opts.timeout_scheduler, opts.async_scheduler.take()) {
+                                (None, Some(scheduler)) => {
+                                    opts.next_delay =
+                                        Some(next_delay(scheduler, elapsed, opts.clock.now()));
+                                    RetryState::Deciding
+                                }
+                                (timeout_scheduler, _) => {
+                                    let scheduler =
+                                        timeout_scheduler.as_mut().unwrap_or(&mut opts.scheduler);
+                                    scheduler.observe_error("timeout");
+                                    scheduler.observe_attempt(elapsed);
+                                    scheduler.observe_time(opts.clock.now());
+                                    RetryState::Decided {
+                                        retry_after: scheduler.next_retry(),
+                                        deadline: deadline_of(&**scheduler),
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+                RetryState::Deciding => {
+                    let _enter = opts.span.enter();
+                    match opts.next_delay.as_mut().unwrap().as_mut().poll(ctx) {
+                        Poll::Pending => return Poll::Pending,
+                        Poll::Ready((scheduler, retry_after)) => {
+                            opts.next_delay = None;
+                            opts.async_scheduler = Some(scheduler);
+                            RetryState::Decided {
+                                retry_after,
+                                deadline: None,
+                            }
+                        }
+                    }
+                }
+                RetryState::Decided {
+                    mut retry_after,
+                    deadline,
+                } => {
+                    let _enter = opts.span.enter();
+                    if let Some(delay) = opts.context.next_delay.take() {
+                        retry_after = retry_after.map(|_| delay);
+                    }
+                    // Check the budget first, so the report doesn't announce a refused retry.
+                    let refused = retry_after.is_some()
+                        && opts
+                            .budget
+                            .as_ref()
+                            .is_some_and(|budget| !budget.try_withdraw());
+                    match &opts.last_error {
+                        Some(err) => {
+                            let weights = opts
+                                .classifiers
+                                .iter()
+                                .filter_map(|classifier| classifier.weight(err));
+                            if let Some(weight) = weights.reduce(|a, b| a * b) {
+                                retry_after = retry_after.map(|delay| scale_delay(delay, weight));
+                            }
+
+                            // log error
+                            let report = match &mut opts.error_dedup {
+                                Some(dedup) => {
+                                    let key = match &opts.error_label {
+                                        Some(label) => label(err).to_string(),
+                                        None => format!("{:?}", err),
+                                    };
+                                    dedup.observe(key, opts.clock.now())
+                                }
+                                None => true,
+                            };
+                            if report {
+                                let next_retry = retry_after.filter(|_| !refused);
+                                this.retryable.report_attempt_error(
+                                    err,
+                                    next_retry,
+                                    &opts.context.attempt,
+                                );
+                            }
+                        }
+                        None => {
+                            // log timeout
+                            let timeout = opts.attempt_timeout.unwrap();
+                            this.retryable
+                                .report_timeout(timeout, retry_after.filter(|_| !refused));
+                        }
+                    }
+
+                    match retry_after {
+                        None => {
+                            audit(&mut opts.audit, opts.attempt, |attempt| {
+                                AuditRecord::Exhausted { attempt }
+                            });
+                            opts.stats.exhausted();
+                            return Poll::Ready(Err(gave_up(
+                                opts.attempt,
+                                &mut opts.last_error,
+                                opts.attempt_timeout,
+                            )));
+                        }
+                        Some(_) if refused => {
+                            audit(&mut opts.audit, opts.attempt, |attempt| {
+                                AuditRecord::Aborted {
+                                    attempt,
+                                    reason: "budget",
+                                }
+                            });
+                            opts.stats.exhausted();
+                            return Poll::Ready(Err(gave_up(
+                                opts.attempt,
+                                &mut opts.last_error,
+                                opts.attempt_timeout,
+                            )));
+                        }
+                        Some(mut retry_after) => {
+                            if opts.interval_pacing {
+                                let took = opts.clock.now() - opts.attempt_started;
+                                retry_after = retry_after.saturating_sub(took);
+                            }
+                            // Don't sleep past the deadline only to give up once awake.
+                            let mut state = RetryState::Waiting;
+                            if let Some(deadline) = deadline {
+                                let left = deadline.saturating_duration_since(opts.clock.now());
+                                let expected = opts.stats.health().latency;
+                                if opts.predictive_abort
+                                    && retry_after.saturating_add(expected) > left
+                                {
+                                    audit(&mut opts.audit, opts.attempt, |attempt| {
+                                        AuditRecord::Aborted {
+                                            attempt,
+                                            reason: "deadline",
+                                        }
+                                    });
+                                    opts.stats.exhausted();
+                                    return Poll::Ready(Err(gave_up(
+                                        opts.attempt,
+                                        &mut opts.last_error,
+                                        opts.attempt_timeout,
+                                    )));
+                                }
+                                if left < retry_after {
+                                    retry_after = left;
+                                    state = RetryState::Expiring;
+                                }
+                            }
+                            audit(&mut opts.audit, opts.attempt, |attempt| {
+                                AuditRecord::Waiting {
+                                    attempt,
+                                    delay: retry_after,
+                                }
+                            });
+                            if let Some(f) = &mut opts.on_retry {
+                                let info = Attempt {
+                                    elapsed: opts.clock.now() - opts.started.unwrap(),
+                                    ..opts.context.attempt
+                                };
+                                f(&info, retry_after);
+                            }
+                            opts.context.attempt.previous_delay = Some(retry_after);
+                            this.trying_fut.set(None);
+                            opts.timeout_fut = None;
+                            opts.waiting_fut =
+                                Some(opts.clock.sleep_coarse(retry_after, opts.granularity));
+                            opts.wait_side =
+                                opts.on_wait.as_ref().map(|f| f(opts.attempt, retry_after));
+                            if let Some(registration) = &mut opts.registration {
+                                registration.update(
+                                    opts.attempt,
+                                    registry::RetryStatus::Waiting,
+                                    Some(opts.clock.now() + retry_after),
+                                );
+                            }
+                            state
+                        }
+                    }
+                }
+                RetryState::Waiting => {
+                    poll_wait_side(&mut opts.wait_side, ctx);
+                    match Pin::new(opts.waiting_fut.as_mut().unwrap()).poll(ctx) {
+                        Poll::Pending => return Poll::Pending,
+                        Poll::Ready(_) => {
+                            opts.wait_side = None;
+                            RetryState::Pending
+                        }
+                    }
+                }
+                RetryState::Expiring => {
+                    poll_wait_side(&mut opts.wait_side, ctx);
+                    match Pin::new(opts.waiting_fut.as_mut().unwrap()).poll(ctx) {
+                        Poll::Pending => return Poll::Pending,
+                        Poll::Ready(_) => {
+                            opts.wait_side = None;
+                            audit(&mut opts.audit, opts.attempt, |attempt| {
+                                AuditRecord::Exhausted { attempt }
+                            });
+                            opts.stats.exhausted();
+                            return Poll::Ready(Err(gave_up(
+                                opts.attempt,
+                                &mut opts.last_error,
+                                opts.attempt_timeout,
+                            )));
+                        }
+                    }
+                }
+            };
+        }
+    }
+}
+
+/// Log the error of a failed attempt, the default of `Retryable::report_error`.
+pub(crate) fn report_error<E>(error: &E, next_retry: Option<Duration>)
+where
+    E: std::fmt::Debug,
+{
+    tracing::error!(
+        "error after retry: {:?} (will retry in {:?})",
+        error,
+        next_retry
+    );
+}
+
+/// The error of a retry whose backoff gave up after `attempts`.
+fn gave_up<E>(
+    attempts: u32,
+    last_error: &mut Option<E>,
+    timeout: Option<Duration>,
+) -> RetryError<E> {
+    match last_error.take() {
+        Some(error) => RetryError::Exhausted { attempts, error },
+        None => RetryError::TimedOut {
+            attempts,
+            timeout: timeout.unwrap_or_default(),
+        },
+    }
+}
+
+/// Write the record made by `f` if the retry is audited.
+fn audit(audit: &mut Option<audit::Audit>, attempt: u32, f: impl FnOnce(u32) -> AuditRecord) {
+    if let Some(audit) = audit {
+        audit.record(f(attempt));
+    }
+}
+
+/// Poll the future started by `Retry::on_wait`, dropping it once it's done.
+fn poll_wait_side(side: &mut Option<WaitFuture>, ctx: &mut Context<'_>) {
+    if let Some(fut) = side {
+        if fut.as_mut().poll(ctx).is_ready() {
+            *side = None;
+        }
+    }
+}
+
+/// Multiply `delay` by `weight`, ignoring negative and non-finite weights.
+fn scale_delay(delay: Duration, weight: f64) -> Duration {
+    if !weight.is_finite() || weight < 0.0 {
+        return delay;
+    }
+    Duration::try_from_secs_f64(delay.as_secs_f64() * weight).unwrap_or(Duration::MAX)
+}
+
+/// Ask `scheduler` for the delay after an attempt that took `elapsed` and finished at `now`,
+/// handing it back along with the delay.
+fn next_delay(mut scheduler: Box<dyn AsyncBackoff>, elapsed: Duration, now: Instant) -> NextDelay {
+    scheduler.attempt_finished(elapsed);
+    scheduler.deciding_at(now);
+    Box::pin(async move {
+        let delay = scheduler.next_delay().await;
+        (scheduler, delay)
+    })
+}
+
+/// The deadline `scheduler` gives up at, if any.
+fn deadline_of(scheduler: &dyn Backoff) -> Option<Instant> {
+    let mut inspection = Inspection::default();
+    scheduler.inspect(&mut inspection);
+    inspection.deadline
+}
+
+impl<F, Fut, I, E> Retryable for F
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<I, E>>,
+    E: std::fmt::Debug,
+{
+    type Item = I;
+    type Error = E;
+    type Future = Fut;
+
+    fn call(&self) -> Self::Future {
+        self()
+    }
+}
+
+/// Share one task between multiple `Retry` drivers.
+///
+/// There is no impl for `&R` as it would overlap with the impl for closures, references to
+/// closures are retryable through that impl already.
+impl<R> Retryable for std::sync::Arc<R>
+where
+    R: Retryable,
+{
+    type Item = R::Item;
+    type Error = R::Error;
+    type Future = R::Future;
+
+    fn call(&self) -> Self::Future {
+        (**self).call()
+    }
+
+    fn call_attempt(&self, attempt: &Attempt) -> Self::Future {
+        (**self).call_attempt(attempt)
+    }
+
+    fn call_with_context(&self, context: &AttemptContext) -> Self::Future {
+        (**self).call_with_context(context)
+    }
+
+    fn metric_labels(&self) -> &[(&str, String)] {
+        (**self).metric_labels()
+    }
+
+    fn classify(&self, error: &Self::Error) -> ErrorKind {
+        (**self).classify(error)
+    }
+
+    fn report_error(&self, error: &Self::Error, next_retry: Option<Duration>) {
+        (**self).report_error(error, next_retry)
+    }
+
+    fn report_attempt_error(
+        &self,
+        error: &Self::Error,
+        next_retry: Option<Duration>,
+        attempt: &Attempt,
+    ) {
+        (**self).report_attempt_error(error, next_retry, attempt)
+    }
+
+    fn report_timeout(&self, timeout: Duration, next_retry: Option<Duration>) {
+        (**self).report_timeout(timeout, next_retry)
+    }
+}
+
+/// Retryable returned by `Retry::boxed_attempts`
+pub struct BoxedAttempts<R>(R);
+
+impl<R> Retryable for BoxedAttempts<R>
+where
+    R: Retryable,
+{
+    type Item = R::Item;
+    type Error = R::Error;
+    type Future = Pin<Box<R::Future>>;
+
+    fn call(&self) -> Self::Future {
+        Box::pin(self.0.call())
+    }
+
+    fn call_attempt(&self, attempt: &Attempt) -> Self::Future {
+        Box::pin(self.0.call_attempt(attempt))
+    }
+
+    fn call_with_context(&self, context: &AttemptContext) -> Self::Future {
+        Box::pin(self.0.call_with_context(context))
+    }
+
+    fn metric_labels(&self) -> &[(&str, String)] {
+        self.0.metric_labels()
+    }
+
+    fn classify(&self, error: &Self::Error) -> ErrorKind {
+        self.0.classify(error)
+    }
+
+    fn report_error(&self, error: &Self::Error, next_retry: Option<Duration>) {
+        self.0.report_error(error, next_retry)
+    }
+
+    fn report_attempt_error(
+        &self,
+        error: &Self::Error,
+        next_retry: Option<Duration>,
+        attempt: &Attempt,
+    ) {
+        self.0.report_attempt_error(error, next_retry, attempt)
+    }
+
+    fn report_timeout(&self, timeout: Duration, next_retry: Option<Duration>) {
+        self.0.report_timeout(timeout, next_retry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_timer::Delay;
+    use std::sync::{Arc, Mutex};
+    use std::task::Waker;
+
+    #[test]
+    fn it_works() {
+        assert_eq!(2 + 2, 4);
+    }
+
+    #[test]
+    fn test_attempt_span() {
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let seen = attempts.clone();
+        let res = block_on(
+            retry(
+                || async { Err::<(), _>("failed") },
+                instant().num_attempts(3),
+            )
+            .attempt_span(move |attempt| {
+                seen.lock().unwrap().push(attempt);
+                tracing::info_span!("attempt", attempt)
+            }),
+        );
+        assert!(res.is_err());
+        assert_eq!(*attempts.lock().unwrap(), vec![1, 2, 3]);
+    }
+
+    /// Records the parents of spans and the links between them.
+    #[derive(Clone, Default)]
+    struct SpanTree(Arc<Mutex<SpanTreeState>>);
+
+    #[derive(Default)]
+    struct SpanTreeState {
+        spans: Vec<Option<u64>>,
+        follows: Vec<(u64, u64)>,
+        entered: Vec<u64>,
+    }
+
+    impl tracing::Subscriber for SpanTree {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, attrs: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            let mut state = self.0.lock().unwrap();
+            let parent = match attrs.parent() {
+                Some(parent) => Some(parent.into_u64()),
+                None if attrs.is_contextual() => state.entered.last().copied(),
+                None => None,
+            };
+            state.spans.push(parent);
+            tracing::span::Id::from_u64(state.spans.len() as u64)
+        }
+
+        fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+
+        fn record_follows_from(&self, span: &tracing::span::Id, follows: &tracing::span::Id) {
+            let mut state = self.0.lock().unwrap();
+            state.follows.push((span.into_u64(), follows.into_u64()));
+        }
+
+        fn event(&self, _: &tracing::Event<'_>) {}
+
+        fn enter(&self, span: &tracing::span::Id) {
+            self.0.lock().unwrap().entered.push(span.into_u64());
+        }
+
+        fn exit(&self, _: &tracing::span::Id) {
+            self.0.lock().unwrap().entered.pop();
+        }
+    }
+
+    #[test]
+    fn test_parent_span() {
+        let tree = SpanTree::default();
+        tracing::subscriber::with_default(tree.clone(), || {
+            let request = tracing::info_span!("request");
+            let fut = retry(
+                || async { Err::<(), _>("failed") },
+                constant(Duration::from_millis(5)).num_attempts(3),
+            )
+            .parent_span(request)
+            .attempt_span(|attempt| tracing::info_span!("attempt", attempt))
+            .link_attempts();
+            assert!(block_on(fut).is_err());
+        });
+        let state = tree.0.lock().unwrap();
+        assert_eq!(state.spans, vec![None, Some(1), Some(1), Some(1)]);
+        assert_eq!(state.follows, vec![(3, 2), (4, 3)]);
+    }
+
+    #[test]
+    fn test_label_errors() {
+        let errors = Arc::new(Mutex::new(vec!["refused", "rate_limited", "rate_limited"]));
+        let task = move || {
+            let error = errors.lock().unwrap().pop();
+            async move {
+                match error {
+                    Some(error) => Err(error),
+                    None => Ok(()),
+                }
+            }
+        };
+        let report = Simulation::new().run(
+            retry(
+                task,
+                reset_on_error_change(|| constant(Duration::from_secs(1)).exponential()),
+            )
+            .label_errors(|error: &&str| *error),
+        );
+        assert!(report.result.is_ok());
+        assert_eq!(
+            report.delays(),
+            vec![
+                Duration::from_secs(1),
+                Duration::from_secs(2),
+                Duration::from_secs(1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deadline_cuts_wait() {
+        let start = Instant::now();
+        let res = block_on(retry(
+            || async { Err::<(), _>("failed") },
+            constant(Duration::from_secs(600)).deadline(start + Duration::from_millis(50)),
+        ));
+        assert!(res.is_err());
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        let start = Instant::now();
+        let res = block_on(retry(
+            || async { Err::<(), _>("failed") },
+            constant(Duration::from_millis(40))
+                .deadline(start + Duration::from_millis(10))
+                .min_attempts(2, Duration::from_millis(1)),
+        ));
+        assert!(res.is_err());
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+
+    #[test]
+    fn test_predictive_abort() {
+        let ms = Duration::from_millis;
+        let run = |predictive: bool| {
+            let simulation = Simulation::new();
+            let clock = simulation.clock();
+            let deadline = clock.now() + ms(120);
+            let fut = retry(
+                move || {
+                    let latency = clock.sleep(ms(50));
+                    async {
+                        latency.await;
+                        Err::<(), _>("failed")
+                    }
+                },
+                constant(ms(30)).deadline(deadline),
+            );
+            let fut = if predictive {
+                fut.predictive_abort()
+            } else {
+                fut
+            };
+            let report = simulation.run(fut);
+            assert!(report.result.is_err());
+            report.attempts
+        };
+        assert_eq!(run(true), vec![ms(0)]);
+        assert_eq!(run(false), vec![ms(0), ms(80)]);
+
+        // A huge delay must not overflow when the expected latency is added.
+        let simulation = Simulation::new();
+        let clock = simulation.clock();
+        let deadline = clock.now() + Duration::from_secs(3600);
+        let report = simulation.run(
+            retry(
+                move || {
+                    let latency = clock.sleep(ms(1));
+                    async {
+                        latency.await;
+                        Err::<(), _>("failed")
+                    }
+                },
+                constant(Duration::MAX).deadline(deadline),
+            )
+            .predictive_abort(),
+        );
+        assert_eq!(
+            report.result,
+            Err(RetryError::Exhausted {
+                attempts: 1,
+                error: "failed"
+            })
+        );
+    }
+
+    #[test]
+    fn test_timer_granularity() {
+        let ms = Duration::from_millis;
+        let report = Simulation::new().run(
+            retry(
+                || async { Err::<(), _>("failed") },
+                constant(ms(10)).num_attempts(3),
+            )
+            .timer_granularity(ms(50)),
+        );
+        assert!(report.result.is_err());
+        assert_eq!(report.attempts, vec![ms(0), ms(50), ms(100)]);
+    }
+
+    #[test]
+    fn test_classifier_weight() {
+        let start = Instant::now();
+        let res = block_on(
+            retry(
+                || async { Err::<(), _>("throttled") },
+                constant(Duration::from_millis(20)).num_attempts(2),
+            )
+            .classifier((|_: &&str| ErrorKind::Transient).weighted(|_: &&str| Some(5.0))),
+        );
+        assert!(res.is_err());
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_retry_if() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let trail = records.clone();
+        let res = block_on(
+            retry_if(
+                move || {
+                    let call = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                    async move { Err::<(), _>(if call < 3 { 503 } else { 404 }) }
+                },
+                instant().num_attempts(10),
+                |status: &u16| *status >= 500,
+            )
+            .audit(move |record| -> AuditWrite {
+                trail.lock().unwrap().push(record);
+                Box::pin(async {})
+            }),
+        );
+        assert_eq!(
+            res,
+            Err(RetryError::Aborted {
+                attempts: 3,
+                error: Some(404)
+            })
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(
+            records.lock().unwrap().last(),
+            Some(&AuditRecord::Aborted {
+                attempt: 3,
+                reason: "permanent error"
+            })
+        );
+    }
+
+    #[test]
+    fn test_retryable_classify() {
+        struct Fetch;
+
+        impl Retryable for Fetch {
+            type Item = ();
+            type Error = &'static str;
+            type Future = std::future::Ready<Result<(), &'static str>>;
+
+            fn call(&self) -> Self::Future {
+                std::future::ready(Err("not found"))
+            }
+
+            fn classify(&self, error: &Self::Error) -> ErrorKind {
+                match *error {
+                    "not found" => ErrorKind::Permanent,
+                    _ => ErrorKind::Transient,
+                }
+            }
+        }
+
+        let res = block_on(retry(Fetch, instant().num_attempts(5)));
+        assert_eq!(res.unwrap_err().attempts(), 1);
+        let res = block_on(retry(idempotent(Fetch), instant().num_attempts(5)));
+        assert_eq!(res.unwrap_err().attempts(), 1);
+        let res = block_on(retry(
+            FailureInjector::new(Fetch, || "injected"),
+            instant().num_attempts(5),
+        ));
+        assert_eq!(res.unwrap_err().attempts(), 1);
+    }
+
+    #[test]
+    fn test_interval_pacing() {
+        let ms = Duration::from_millis;
+        let simulation = Simulation::new();
+        let clock = simulation.clock();
+        let report = simulation.run(
+            retry(
+                move || {
+                    let latency = clock.sleep(ms(40));
+                    async {
+                        latency.await;
+                        Err::<(), _>("failed")
+                    }
+                },
+                constant(ms(100)).num_attempts(3),
+            )
+            .interval_pacing(),
+        );
+        assert!(report.result.is_err());
+        assert_eq!(report.attempts, vec![ms(0), ms(100), ms(200)]);
+    }
+
+    #[test]
+    fn test_dedup_errors() {
+        struct Task {
+            errors: Mutex<Vec<&'static str>>,
+            reports: Mutex<Vec<&'static str>>,
+        }
+
+        impl Retryable for Task {
+            type Item = ();
+            type Error = &'static str;
+            type Future = std::future::Ready<Result<(), &'static str>>;
+
+            fn call(&self) -> Self::Future {
+                let error = self.errors.lock().unwrap().pop().unwrap_or("done");
+                std::future::ready(Err(error))
+            }
+
+            fn report_error(&self, error: &Self::Error, _next_retry: Option<Duration>) {
+                self.reports.lock().unwrap().push(error);
+            }
+        }
+
+        let task = Arc::new(Task {
+            errors: Mutex::new(vec!["refused", "timeout", "timeout", "timeout", "refused"]),
+            reports: Mutex::new(Vec::new()),
+        });
+        let res = block_on(
+            retry(task.clone(), instant().num_attempts(5)).dedup_errors(Duration::from_secs(60)),
+        );
+        assert!(res.is_err());
+        assert_eq!(
+            *task.reports.lock().unwrap(),
+            vec!["refused", "timeout", "refused"]
+        );
+    }
+
+    #[test]
+    fn test_watchdog() {
+        let ms = Duration::from_millis;
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        let seen = fired.clone();
+        let report = Simulation::new().run(
+            retry(
+                || async { Err::<(), _>("failed") },
+                constant(ms(20)).num_attempts(5),
+            )
+            .watchdog(ms(30), move |attempts, elapsed| {
+                seen.lock().unwrap().push((attempts, elapsed));
+            }),
+        );
+        assert!(report.result.is_err());
+        assert_eq!(*fired.lock().unwrap(), vec![(2, ms(30))]);
+
+        // Fires while the attempt is still running.
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        let seen = fired.clone();
+        let simulation = Simulation::new();
+        let clock = simulation.clock();
+        let report = simulation.run(
+            retry(
+                move || {
+                    let latency = clock.sleep(ms(100));
+                    async {
+                        latency.await;
+                        Err::<(), _>("failed")
+                    }
+                },
+                instant().num_attempts(1),
+            )
+            .watchdog(ms(20), move |attempts, elapsed| {
+                seen.lock().unwrap().push((attempts, elapsed));
+            }),
+        );
+        assert!(report.result.is_err());
+        assert_eq!(*fired.lock().unwrap(), vec![(1, ms(20))]);
+    }
+
+    #[test]
+    fn test_audit() {
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let trail = records.clone();
+        let calls = Arc::new(Mutex::new(0));
+        let counter = calls.clone();
+        let res = block_on(
+            retry(
+                move || {
+                    let mut calls = counter.lock().unwrap();
+                    *calls += 1;
+                    let call = *calls;
+                    async move {
+                        if call < 2 {
+                            Err("refused")
+                        } else {
+                            Ok(call)
+                        }
+                    }
+                },
+                constant(Duration::from_millis(1)),
+            )
+            .audit(move |record| -> AuditWrite {
+                let trail = trail.clone();
+                Box::pin(async move {
+                    // A slow sink holds the retry back instead of losing records.
+                    Delay::new(Duration::from_millis(5)).await;
+                    trail.lock().unwrap().push(record);
+                })
+            }),
+        );
+        assert_eq!(res.unwrap(), 2);
+        assert_eq!(
+            *records.lock().unwrap(),
+            vec![
+                AuditRecord::AttemptStarted { attempt: 1 },
+                AuditRecord::AttemptFailed {
+                    attempt: 1,
+                    error: "\"refused\"".to_string()
+                },
+                AuditRecord::Waiting {
+                    attempt: 1,
+                    delay: Duration::from_millis(1)
+                },
+                AuditRecord::AttemptStarted { attempt: 2 },
+                AuditRecord::Succeeded { attempt: 2 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_on_wait() {
+        let waits = Arc::new(Mutex::new(Vec::new()));
+        let heartbeats = Arc::new(Mutex::new(0));
+        let seen = waits.clone();
+        let beats = heartbeats.clone();
+        let simulation = Simulation::new();
+        let clock = simulation.clock();
+        let report = simulation.run(
+            retry(
+                || async { Err::<(), _>("failed") },
+                constant(Duration::from_millis(30)).num_attempts(3),
+            )
+            .on_wait(move |attempt, delay| {
+                seen.lock().unwrap().push((attempt, delay));
+                let beats = beats.clone();
+                let clock = clock.clone();
+                async move {
+                    loop {
+                        *beats.lock().unwrap() += 1;
+                        clock.sleep(Duration::from_millis(10)).await;
+                    }
+                }
+            }),
+        );
+        assert!(report.result.is_err());
+        let delay = Duration::from_millis(30);
+        assert_eq!(*waits.lock().unwrap(), vec![(1, delay), (2, delay)]);
+        // Beats at 0, 10, 20 and 30ms into each wait, the side future being polled before the
+        // wait completes.
+        assert_eq!(*heartbeats.lock().unwrap(), 8);
+    }
+
+    #[test]
+    fn test_timeout_per_attempt() {
+        let attempts = Arc::new(Mutex::new(0));
+        let counter = attempts.clone();
+        let res = block_on(
+            retry(
+                move || {
+                    let counter = counter.clone();
+                    async move {
+                        let attempt = {
+                            let mut attempts = counter.lock().unwrap();
+                            *attempts += 1;
+                            *attempts
+                        };
+                        if attempt < 3 {
+                            Delay::new(Duration::from_secs(10)).await;
+                        }
+                        Ok::<_, ()>(attempt)
+                    }
+                },
+                constant(Duration::from_secs(10)),
+            )
+            .timeout_per_attempt(Duration::from_millis(10))
+            .timeout_backoff(instant()),
+        );
+        assert_eq!(res.unwrap(), 3);
+    }
+
+    #[test]
+    fn test_timeout_schedule() {
+        let attempts = Arc::new(Mutex::new(0));
+        let counter = attempts.clone();
+        let res = block_on(
+            retry(
+                move || {
+                    *counter.lock().unwrap() += 1;
+                    async {
+                        Delay::new(Duration::from_millis(30)).await;
+                        Ok::<_, ()>(())
+                    }
+                },
+                instant(),
+            )
+            .timeout_schedule(sequence(vec![
+                Duration::from_millis(1),
+                Duration::from_millis(10),
+                Duration::from_millis(100),
+            ])),
+        );
+        assert!(res.is_ok());
+        assert_eq!(*attempts.lock().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_middleware() {
+        struct AbortAfter(u32, Arc<Mutex<Vec<String>>>);
+
+        impl Middleware<(), &'static str> for AbortAfter {
+            fn before_attempt(&mut self, context: &mut AttemptContext) -> Control {
+                let attempt = context.attempt.number;
+                self.1.lock().unwrap().push(format!("before {}", attempt));
+                Control::Continue
+            }
+
+            fn after_failure(
+                &mut self,
+                context: &mut AttemptContext,
+                error: &&'static str,
+            ) -> Control {
+                let attempt = context.attempt.number;
+                self.1
+                    .lock()
+                    .unwrap()
+                    .push(format!("{} {}", error, attempt));
+                if attempt >= self.0 {
+                    Control::Abort
+                } else {
+                    Control::Continue
+                }
+            }
+        }
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let res = block_on(
+            retry(|| async { Err::<(), _>("failed") }, instant())
+                .middleware(AbortAfter(2, log.clone())),
+        );
+        assert!(res.is_err());
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["before 1", "failed 1", "before 2", "failed 2"]
+        );
+    }
+
+    #[test]
+    fn test_middleware_context() {
+        struct Auth;
+
+        impl Middleware<(), &'static str> for Auth {
+            fn before_attempt(&mut self, context: &mut AttemptContext) -> Control {
+                context
+                    .metadata
+                    .entry("token".to_string())
+                    .or_insert_with(|| "stale".to_string());
+                Control::Continue
+            }
+
+            fn after_failure(&mut self, context: &mut AttemptContext, _error: &&str) -> Control {
+                context
+                    .metadata
+                    .insert("token".to_string(), "fresh".to_string());
+                context.next_delay = Some(Duration::from_millis(1));
+                Control::Continue
+            }
+        }
+
+        struct Task {
+            tokens: Mutex<Vec<String>>,
+        }
+
+        impl Retryable for Task {
+            type Item = ();
+            type Error = &'static str;
+            type Future = std::future::Ready<Result<(), &'static str>>;
+
+            fn call(&self) -> Self::Future {
+                std::future::ready(Err("unauthorized"))
+            }
+
+            fn call_with_context(&self, context: &AttemptContext) -> Self::Future {
+                let token = context.metadata["token"].clone();
+                let res = if token == "fresh" {
+                    Ok(())
+                } else {
+                    Err("unauthorized")
+                };
+                self.tokens.lock().unwrap().push(token);
+                std::future::ready(res)
+            }
+        }
+
+        let task = Arc::new(Task {
+            tokens: Mutex::new(Vec::new()),
+        });
+        let start = Instant::now();
+        let res = block_on(retry(task.clone(), constant(Duration::from_secs(60))).middleware(Auth));
+        assert!(res.is_ok());
+        assert!(start.elapsed() < Duration::from_secs(60));
+        assert_eq!(*task.tokens.lock().unwrap(), vec!["stale", "fresh"]);
+    }
+
+    #[test]
+    fn test_boxed() {
+        let retries: Vec<BoxRetry<u32, &str>> = vec![
+            retry(|| async { Ok::<_, &str>(1) }, instant()).boxed(),
+            retry(
+                || async { Ok::<_, &str>(2) },
+                constant(Duration::from_secs(1)),
+            )
+            .boxed(),
+        ];
+        let results: Vec<u32> = retries.into_iter().map(|r| block_on(r).unwrap()).collect();
+        assert_eq!(results, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_boxed_attempts() {
+        let task = || async {
+            let buf = [0u8; 4096];
+            Delay::new(Duration::from_millis(1)).await;
+            Ok::<_, ()>(buf.len())
+        };
+        let inline = retry(task, instant());
+        let boxed = retry(task, instant()).boxed_attempts();
+        assert!(std::mem::size_of_val(&boxed) < std::mem::size_of_val(&inline));
+        assert_eq!(block_on(boxed).unwrap(), 4096);
+    }
+
+    #[test]
+    fn test_shared_task() {
+        let task = Arc::new(FailureInjector::new(
+            || async { Ok::<_, &str>(()) },
+            || "injected",
+        ));
+        block_on(retry(task.clone(), instant())).unwrap();
+        block_on(retry(task.clone(), instant())).unwrap();
+        assert_eq!(task.calls(), 2);
+
+        let closure = || async { Ok::<_, ()>(1) };
+        assert_eq!(block_on(retry(&closure, instant())).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_retry_ref() {
+        struct Client {
+            responses: Mutex<Vec<Result<u32, ()>>>,
+        }
+
+        struct Request<'a> {
+            client: &'a Client,
+        }
+
+        impl Future for Request<'_> {
+            type Output = Result<u32, ()>;
+
+            fn poll(self: Pin<&mut Self>, _ctx: &mut Context<'_>) -> Poll<Self::Output> {
+                Poll::Ready(self.client.responses.lock().unwrap().remove(0))
+            }
+        }
+
+        impl RetryableRef for Client {
+            type Item = u32;
+            type Error = ();
+            type Future<'a> = Request<'a>;
+
+            fn call(&self) -> Self::Future<'_> {
+                Request { client: self }
+            }
+        }
+
+        let client = Client {
+            responses: Mutex::new(vec![Err(()), Err(()), Ok(3)]),
+        };
+        assert_eq!(block_on(retry_ref(&client, instant())).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_lifecycle_hooks() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let (retries, successes) = (log.clone(), log.clone());
+        let calls = Arc::new(Mutex::new(0));
+        let res = block_on(
+            retry(
+                move || {
+                    let mut calls = calls.lock().unwrap();
+                    *calls += 1;
+                    let call = *calls;
+                    async move {
+                        if call < 3 {
+                            Err("unavailable")
+                        } else {
+                            Ok(call)
+                        }
+                    }
+                },
+                constant(Duration::from_millis(5)),
+            )
+            .on_retry(move |attempt, delay| {
+                retries
+                    .lock()
+                    .unwrap()
+                    .push(format!("retry {} in {:?}", attempt.number, delay))
+            })
+            .on_success(move |attempt| {
+                successes
+                    .lock()
+                    .unwrap()
+                    .push(format!("success {}", attempt.number))
+            }),
+        );
+        assert_eq!(res, Ok(3));
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["retry 1 in 5ms", "retry 2 in 5ms", "success 3"]
+        );
+
+        let gave_up = Arc::new(Mutex::new(None));
+        let seen = gave_up.clone();
+        let res = block_on(
+            retry(
+                || async { Err::<(), _>("unavailable") },
+                instant().num_attempts(2),
+            )
+            .on_give_up(move |attempt, err| {
+                *seen.lock().unwrap() = Some((attempt.number, err.attempts()))
+            }),
+        );
+        assert!(res.is_err());
+        assert_eq!(*gave_up.lock().unwrap(), Some((2, 2)));
+    }
+
+    #[test]
+    fn test_budget() {
+        let budget = RetryBudget::new(0.5, 0);
+        let res = block_on(retry(|| async { Ok::<_, &str>(()) }, instant()).budget(budget.clone()));
+        assert!(res.is_ok());
+        assert_eq!(budget.remaining(), 0);
+        budget.deposit();
+
+        struct Task {
+            reports: Mutex<Vec<Option<Duration>>>,
+        }
+
+        impl Retryable for Task {
+            type Item = ();
+            type Error = &'static str;
+            type Future = std::future::Ready<Result<(), &'static str>>;
+
+            fn call(&self) -> Self::Future {
+                std::future::ready(Err("unavailable"))
+            }
+
+            fn report_error(&self, _error: &Self::Error, next_retry: Option<Duration>) {
+                self.reports.lock().unwrap().push(next_retry);
+            }
+        }
+
+        let task = Arc::new(Task {
+            reports: Mutex::new(Vec::new()),
+        });
+        let res = block_on(retry(task.clone(), instant().num_attempts(10)).budget(budget.clone()));
+        assert_eq!(
+            res,
+            Err(RetryError::Exhausted {
+                attempts: 2,
+                error: "unavailable"
+            })
+        );
+        assert_eq!(budget.remaining(), 0);
+        // The refused retry is reported as final, not as "will retry".
+        assert_eq!(
+            *task.reports.lock().unwrap(),
+            vec![Some(Duration::from_secs(0)), None]
+        );
+    }
+
+    #[test]
+    fn test_with_cancellation() {
+        let start = Instant::now();
+        let res = block_on(
+            retry(
+                || async { Err::<(), _>("unavailable") },
+                constant(Duration::from_secs(60)),
+            )
+            .with_cancellation(Delay::new(Duration::from_millis(20))),
+        );
+        assert_eq!(
+            res,
+            Err(RetryError::Cancelled {
+                attempts: 1,
+                error: Some("unavailable")
+            })
+        );
+        assert!(start.elapsed() < Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_retry_with_attempt() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = seen.clone();
+        let res = block_on(retry_with_attempt(
+            move |attempt: &Attempt| {
+                log.lock().unwrap().push(*attempt);
+                let number = attempt.number;
+                async move {
+                    if number < 3 {
+                        Err(())
+                    } else {
+                        Ok(number)
+                    }
+                }
+            },
+            constant(Duration::from_millis(20)),
+        ));
+        assert_eq!(res.unwrap(), 3);
+
+        let seen = seen.lock().unwrap();
+        let numbers: Vec<_> = seen.iter().map(|attempt| attempt.number).collect();
+        assert_eq!(numbers, vec![1, 2, 3]);
+        assert!(seen[0].elapsed < Duration::from_millis(20));
+        assert_eq!(seen[0].previous_delay, None);
+        assert_eq!(seen[2].previous_delay, Some(Duration::from_millis(20)));
+        assert!(seen[2].elapsed >= Duration::from_millis(40));
+    }
+
+    #[test]
+    fn test_attempt_through_wrappers() {
+        type Task = WithAttempt<fn(&Attempt) -> std::future::Ready<Result<(), u32>>>;
+
+        fn task() -> Task {
+            WithAttempt {
+                task: |attempt| std::future::ready(Err(attempt.number)),
+            }
+        }
+
+        struct Record<E>(Arc<Mutex<Vec<u32>>>, fn(&E) -> u32);
+
+        impl<E> Middleware<(), E> for Record<E> {
+            fn after_failure(&mut self, _context: &mut AttemptContext, error: &E) -> Control {
+                self.0.lock().unwrap().push((self.1)(error));
+                Control::Continue
+            }
+        }
+
+        fn numbers<R>(task: R, number: fn(&R::Error) -> u32) -> Vec<u32>
+        where
+            R: Retryable<Item = ()>,
+            R::Error: 'static,
+        {
+            let seen = Arc::new(Mutex::new(Vec::new()));
+            let retry = retry(task, instant().num_attempts(3));
+            assert!(block_on(retry.middleware(Record(seen.clone(), number))).is_err());
+            let seen = seen.lock().unwrap();
+            seen.clone()
+        }
+
+        let number = |error: &u32| *error;
+        let expected = vec![1, 2, 3];
+        assert_eq!(numbers(task(), number), expected);
+        assert_eq!(numbers(idempotent(task()), number), expected);
+        assert_eq!(
+            numbers(FailureInjector::new(task(), || 0), number),
+            expected
+        );
+        assert_eq!(numbers(Arc::new(task()), number), expected);
+        assert_eq!(numbers(BoxedAttempts(task()), number), expected);
+        let breaker = CircuitBreaker::new(10, Duration::from_secs(1));
+        let guarded = |error: &CircuitError<u32>| match error {
+            CircuitError::Failed(error) => *error,
+            CircuitError::Open => 0,
+        };
+        assert_eq!(numbers(breaker.wrap(task()), guarded), expected);
+    }
+
+    #[test]
+    fn test_retry_mut() {
+        let mut cursor = 0;
+        let res = block_on(retry_mut(
+            move || {
+                cursor += 10;
+                let offset = cursor;
+                async move {
+                    if offset < 30 {
+                        Err(offset)
+                    } else {
+                        Ok(offset)
+                    }
+                }
+            },
+            instant(),
+        ));
+        assert_eq!(res.unwrap(), 30);
+    }
+
+    #[test]
+    fn test_retry_async_fn() {
+        let attempts = Mutex::new(0);
+        let task = async || {
+            let mut attempts = attempts.lock().unwrap();
+            *attempts += 1;
+            if *attempts < 3 {
+                Err(())
+            } else {
+                Ok(*attempts)
+            }
+        };
+        assert_eq!(block_on(retry_async_fn(&task, instant())).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_initial_delay() {
+        let start = Instant::now();
+        let res = block_on(
+            retry(|| async { Ok::<_, ()>(()) }, instant()).initial_delay(Duration::from_millis(20)),
+        );
+        assert!(res.is_ok());
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_register() {
+        let is_registered = || {
+            registry::snapshot()
+                .iter()
+                .any(|info| info.name == "test_register")
+        };
+        let fut = retry(|| async { Ok::<_, ()>(()) }, instant()).register("test_register");
+        assert!(is_registered());
+        block_on(fut).unwrap();
+        assert!(!is_registered());
+    }
+
+    #[test]
+    fn test_poll_stats() {
+        let mut fut = Box::pin(
+            retry(
+                || async { Err::<(), _>(()) },
+                constant(Duration::from_secs(60)),
+            )
+            .register("test_poll_stats"),
+        );
+        let mut ctx = Context::from_waker(Waker::noop());
+        for _ in 0..3 {
+            assert!(fut.as_mut().poll(&mut ctx).is_pending());
+        }
+        let info = registry::snapshot()
+            .into_iter()
+            .find(|info| info.name == "test_poll_stats")
+            .unwrap();
+        assert_eq!(info.polls.polls, 3);
+        assert_eq!(info.polls.spurious_wakeups, 2);
+        assert!(info.polls.immediate_repolls <= 2);
+    }
+
+    #[test]
+    fn test_health() {
+        let task = FailureInjector::new(|| async { Ok::<_, &str>(()) }, || "injected")
+            .script(vec![true, true, true]);
+        let mut fut = Box::pin(retry(task, instant()).register("test_health"));
+        assert_eq!(fut.health().samples, 0);
+        block_on(fut.as_mut()).unwrap();
+
+        let health = fut.health();
+        assert_eq!(health.samples, 4);
+        assert!((health.success_rate - 0.2).abs() < 1e-9);
+        assert_eq!(stats::health("test_health"), Some(health));
+        assert_eq!(stats::health("test_health_unknown"), None);
+    }
+
+    #[test]
+    fn test_stats() {
+        let in_progress = || {
+            stats::snapshot()
+                .by_name
+                .get("test_stats")
+                .map_or(0, |counters| counters.in_progress)
+        };
+        let mut fut = Box::pin(
+            retry(
+                || async { Err::<(), _>(()) },
+                constant(Duration::from_secs(60)),
+            )
+            .register("test_stats"),
+        );
+        assert_eq!(in_progress(), 0);
+        let mut ctx = Context::from_waker(Waker::noop());
+        assert!(fut.as_mut().poll(&mut ctx).is_pending());
+        assert_eq!(in_progress(), 1);
+        drop(fut);
+        assert_eq!(in_progress(), 0);
+
+        let fut =
+            retry(|| async { Err::<(), _>(()) }, instant().num_attempts(3)).register("test_stats");
+        assert!(block_on(fut).is_err());
+
+        let snapshot = stats::snapshot();
+        let counters = snapshot.by_name["test_stats"];
+        assert_eq!(counters.in_progress, 0);
+        assert_eq!(counters.attempts, 4);
+        assert_eq!(counters.exhaustions, 1);
+        assert!(snapshot.total.attempts >= 3);
+
+        #[cfg(feature = "serde")]
+        {
+            fn serializable<T: serde::Serialize>(_: &T) {}
+            serializable(&snapshot);
+        }
+    }
+
+    #[test]
+    fn test_metric_labels() {
+        struct Fetch {
+            labels: Vec<(&'static str, String)>,
+        }
+
+        impl Retryable for Fetch {
+            type Item = ();
+            type Error = &'static str;
+            type Future = std::future::Ready<Result<(), &'static str>>;
+
+            fn call(&self) -> Self::Future {
+                std::future::ready(Err("unavailable"))
+            }
+
+            fn metric_labels(&self) -> &[(&str, String)] {
+                &self.labels
+            }
+        }
+
+        let task = Fetch {
+            labels: vec![("endpoint", "eu-1".to_string())],
+        };
+        let fut = retry(task, instant().num_attempts(2)).register("test_metric_labels");
+        let info = registry::snapshot()
+            .into_iter()
+            .find(|info| info.name == "test_metric_labels")
+            .unwrap();
+        assert_eq!(
+            info.labels,
+            vec![("endpoint".to_string(), "eu-1".to_string())]
+        );
+        assert!(block_on(fut).is_err());
+
+        let series = r#"test_metric_labels{endpoint="eu-1"}"#;
+        assert_eq!(stats::snapshot().by_name[series].attempts, 2);
+        assert!(stats::health(series).is_some());
+        assert!(stats::health("test_metric_labels").is_none());
     }
 }
@@ -0,0 +1,107 @@
+use crate::{clock::Clock, Attempt};
+use std::{collections::BTreeMap, time::Duration};
+
+/// Decision returned by a `Middleware` hook.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Control {
+    /// Continue retrying.
+    Continue,
+    /// Stop retrying, the `Retry` resolves with `RetryError::Aborted`.
+    Abort,
+}
+
+/// The context of an attempt, passed to the hooks of a `Middleware`.
+#[derive(Clone, Debug)]
+pub struct AttemptContext {
+    /// The attempt the hook is called for.
+    pub attempt: Attempt,
+    /// Metadata for the task, like headers to inject or a refreshed token.
+    ///
+    /// Passed to `Retryable::call_with_context` and kept across attempts.
+    pub metadata: BTreeMap<String, String>,
+    /// Wait this long before the next attempt instead of the delay of the backoff.
+    ///
+    /// Set it in `after_failure` or `after_timeout`. It doesn't keep a retry going whose
+    /// backoff gave up.
+    pub next_delay: Option<Duration>,
+    /// The clock driving the retry, for tasks which wait on their own.
+    pub(crate) clock: Clock,
+}
+
+/// Middleware intercepts the attempts made by a `Retry`.
+///
+/// Middleware is registered with `Retry::middleware`. `before_attempt` is called in the order
+/// the middleware was registered, the other hooks in reverse order, so each middleware wraps
+/// the ones registered after it. The first middleware returning `Control::Abort` stops the
+/// retry. Hooks may change the `AttemptContext` to pass metadata to the task or adjust the
+/// next delay.
+pub trait Middleware<T, E>: Send {
+    /// Called before an attempt is started.
+    fn before_attempt(&mut self, _context: &mut AttemptContext) -> Control {
+        Control::Continue
+    }
+
+    /// Called after an attempt failed with an error.
+    fn after_failure(&mut self, _context: &mut AttemptContext, _error: &E) -> Control {
+        Control::Continue
+    }
+
+    /// Called after an attempt timed out.
+    fn after_timeout(&mut self, _context: &mut AttemptContext, _timeout: Duration) -> Control {
+        Control::Continue
+    }
+
+    /// Called after an attempt succeeded.
+    fn after_success(&mut self, _context: &AttemptContext, _item: &T) {}
+}
+
+pub(crate) struct Chain<T, E> {
+    layers: Vec<Box<dyn Middleware<T, E>>>,
+}
+
+impl<T, E> Chain<T, E> {
+    pub(crate) fn new() -> Self {
+        Chain { layers: Vec::new() }
+    }
+
+    pub(crate) fn push(&mut self, middleware: Box<dyn Middleware<T, E>>) {
+        self.layers.push(middleware);
+    }
+
+    pub(crate) fn before_attempt(&mut self, context: &mut AttemptContext) -> Control {
+        for layer in self.layers.iter_mut() {
+            if layer.before_attempt(context) == Control::Abort {
+                return Control::Abort;
+            }
+        }
+        Control::Continue
+    }
+
+    pub(crate) fn after_failure(&mut self, context: &mut AttemptContext, error: &E) -> Control {
+        for layer in self.layers.iter_mut().rev() {
+            if layer.after_failure(context, error) == Control::Abort {
+                return Control::Abort;
+            }
+        }
+        Control::Continue
+    }
+
+    pub(crate) fn after_timeout(
+        &mut self,
+        context: &mut AttemptContext,
+        timeout: Duration,
+    ) -> Control {
+        for layer in self.layers.iter_mut().rev() {
+            if layer.after_timeout(context, timeout) == Control::Abort {
+                return Control::Abort;
+            }
+        }
+        Control::Continue
+    }
+
+    pub(crate) fn after_success(&mut self, context: &AttemptContext, item: &T) {
+        for layer in self.layers.iter_mut().rev() {
+            layer.after_success(context, item);
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use crate::{constant, instant, retry, Backoff, Classifier, Retry, Retryable};
+use std::{sync::Arc, time::Duration};
+
+/// A reusable retry configuration.
+///
+/// Bundles the backoff, the attempt limit, the per-attempt timeout and the error
+/// classification of a `Retry`, so one policy can be cloned and shared across every call
+/// site retrying the same kind of operation, like
+/// `RetryPolicy::exponential(Duration::from_millis(100)).max_attempts(5).jitter(0.3)`.
+pub struct RetryPolicy<E> {
+    delay: Delay,
+    max_backoff: Option<Duration>,
+    jitter: Option<f64>,
+    max_attempts: Option<u32>,
+    timeout: Option<Duration>,
+    classifier: Option<Arc<dyn Classifier<E> + Send + Sync>>,
+}
+
+#[derive(Clone, Copy)]
+enum Delay {
+    Instant,
+    Constant(Duration),
+    Exponential(Duration, u32),
+}
+
+impl<E> RetryPolicy<E> {
+    fn new(delay: Delay) -> Self {
+        RetryPolicy {
+            delay,
+            max_backoff: None,
+            jitter: None,
+            max_attempts: None,
+            timeout: None,
+            classifier: None,
+        }
+    }
+
+    /// Retry right away.
+    pub fn instant() -> Self {
+        Self::new(Delay::Instant)
+    }
+
+    /// Wait `delay` between attempts.
+    pub fn constant(delay: Duration) -> Self {
+        Self::new(Delay::Constant(delay))
+    }
+
+    /// Wait `delay` after the first attempt, doubling it every retry.
+    pub fn exponential(delay: Duration) -> Self {
+        Self::new(Delay::Exponential(delay, 2))
+    }
+
+    /// Wait `delay` after the first attempt, multiplying it by `base` every retry.
+    pub fn exponential_by(delay: Duration, base: u32) -> Self {
+        assert!(base > 0, "base must be larger than zero");
+        Self::new(Delay::Exponential(delay, base))
+    }
+
+    /// Never wait longer than `max` between attempts.
+    pub fn max_backoff(mut self, max: Duration) -> Self {
+        self.max_backoff = Some(max);
+        self
+    }
+
+    /// Randomize delays, see `Backoff::jitter`.
+    pub fn jitter(mut self, scale: f64) -> Self {
+        assert!(scale > 0.0, "scale must be larger than zero");
+        assert!(scale <= 1.0, "scale must be smaller or equal to one");
+        self.jitter = Some(scale);
+        self
+    }
+
+    /// Give up after `num` attempts, including the first one.
+    pub fn max_attempts(mut self, num: u32) -> Self {
+        assert!(num > 0, "num must be larger than zero");
+        self.max_attempts = Some(num);
+        self
+    }
+
+    /// Fail attempts that take longer than `timeout`, see `Retry::timeout_per_attempt`.
+    pub fn timeout_per_attempt(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Give up on errors `classifier` deems permanent, see `Retry::classifier`.
+    pub fn classifier<C>(mut self, classifier: C) -> Self
+    where
+        C: Classifier<E> + Send + Sync + 'static,
+    {
+        self.classifier = Some(Arc::new(classifier));
+        self
+    }
+
+    /// Make a fresh backoff following this policy.
+    pub fn backoff(&self) -> Box<dyn Backoff> {
+        let mut backoff: Box<dyn Backoff> = match self.delay {
+            Delay::Instant => Box::new(instant()),
+            Delay::Constant(delay) => Box::new(constant(delay)),
+            Delay::Exponential(delay, base) => Box::new(constant(delay).exponential_by(base)),
+        };
+        if let Some(max) = self.max_backoff {
+            backoff = Box::new(backoff.max_backoff(max));
+        }
+        if let Some(scale) = self.jitter {
+            backoff = Box::new(backoff.jitter(scale));
+        }
+        if let Some(num) = self.max_attempts {
+            backoff = Box::new(backoff.num_attempts(num));
+        }
+        backoff
+    }
+
+    /// Retry `task` following this policy.
+    pub fn retry<R>(&self, task: R) -> Retry<R>
+    where
+        R: Retryable<Error = E>,
+        E: std::fmt::Debug + 'static,
+    {
+        let mut retry = retry(task, self.backoff());
+        if let Some(timeout) = self.timeout {
+            retry = retry.timeout_per_attempt(timeout);
+        }
+        if let Some(classifier) = &self.classifier {
+            retry = retry.classifier(classifier.clone());
+        }
+        retry
+    }
+}
+
+impl<E> Clone for RetryPolicy<E> {
+    fn clone(&self) -> Self {
+        RetryPolicy {
+            delay: self.delay,
+            max_backoff: self.max_backoff,
+            jitter: self.jitter,
+            max_attempts: self.max_attempts,
+            timeout: self.timeout,
+            classifier: self.classifier.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block_on, ErrorKind, RetryError};
+
+    #[test]
+    fn test_retry_policy() {
+        let policy: RetryPolicy<()> = RetryPolicy::exponential(Duration::from_millis(100))
+            .max_backoff(Duration::from_millis(300))
+            .max_attempts(4);
+        let mut bo = policy.backoff();
+        assert_eq!(bo.next_retry(), Some(Duration::from_millis(100)));
+        assert_eq!(bo.next_retry(), Some(Duration::from_millis(200)));
+        assert_eq!(bo.next_retry(), Some(Duration::from_millis(300)));
+        assert_eq!(bo.next_retry(), None);
+
+        let policy = RetryPolicy::instant()
+            .max_attempts(5)
+            .classifier(|status: &u16| {
+                if *status >= 500 {
+                    ErrorKind::Transient
+                } else {
+                    ErrorKind::Permanent
+                }
+            });
+        let shared = policy.clone();
+        let res = block_on(shared.retry(|| async { Err::<(), _>(503) }));
+        assert_eq!(res.unwrap_err().attempts(), 5);
+        let res = block_on(policy.retry(|| async { Err::<(), _>(404) }));
+        assert_eq!(
+            res,
+            Err(RetryError::Aborted {
+                attempts: 1,
+                error: Some(404)
+            })
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use crate::{
+    clock::{Clock, Sleep},
+    retry, AttemptContext, Backoff, ErrorKind, Retry, Retryable,
+};
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    process::{Child, Command, ExitStatus},
+    task::{Context, Poll},
+    thread,
+    time::Duration,
+};
+
+/// Interval at which a running child process is checked for exit.
+///
+/// `std` can't wake a future once a child exits without blocking a thread in `Child::wait`,
+/// so `Exit` checks the child with `try_wait` on the clock of the retry instead.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Run a command until it exits successfully.
+///
+/// The command is rebuilt with `cmd` for every attempt. Attempts fail when the process
+/// can't be spawned or exits with a non-zero status. A running process is checked for exit
+/// every 10ms on the clock of the retry, and killed when its attempt is dropped, e.g. by
+/// `Retry::timeout_per_attempt`.
+pub fn retry_command<F, S>(cmd: F, scheduler: S) -> Retry<RetryCommand<F>>
+where
+    F: Fn() -> Command,
+    S: Backoff + 'static,
+{
+    retry(
+        RetryCommand {
+            cmd,
+            permanent: Vec::new(),
+        },
+        scheduler,
+    )
+}
+
+/// Retryable returned by `retry_command`
+pub struct RetryCommand<F> {
+    cmd: F,
+    permanent: Vec<i32>,
+}
+
+impl<F> Retry<RetryCommand<F>>
+where
+    F: Fn() -> Command,
+{
+    /// Give up right away when the process exits with one of `codes`.
+    ///
+    /// Other exit codes, exits by signal and failures to spawn the process are retried.
+    pub fn permanent_codes(mut self, codes: impl IntoIterator<Item = i32>) -> Self {
+        self.retryable.permanent.extend(codes);
+        self
+    }
+}
+
+impl<F> Retryable for RetryCommand<F>
+where
+    F: Fn() -> Command,
+{
+    type Item = ExitStatus;
+    type Error = CommandError;
+    type Future = Exit;
+
+    fn call(&self) -> Self::Future {
+        Exit::spawn((self.cmd)(), Clock::System)
+    }
+
+    fn call_with_context(&self, context: &AttemptContext) -> Self::Future {
+        Exit::spawn((self.cmd)(), context.clock.clone())
+    }
+
+    fn classify(&self, error: &Self::Error) -> ErrorKind {
+        match error.code() {
+            Some(code) if self.permanent.contains(&code) => ErrorKind::Permanent,
+            _ => ErrorKind::Transient,
+        }
+    }
+}
+
+/// The error of a single command attempt.
+#[derive(Debug)]
+pub enum CommandError {
+    /// The process could not be spawned.
+    Spawn(io::Error),
+    /// Waiting for the process failed.
+    Wait(io::Error),
+    /// The process exited with a non-zero status.
+    Exit(ExitStatus),
+}
+
+impl CommandError {
+    /// The exit code of the process, if it exited with one.
+    pub fn code(&self) -> Option<i32> {
+        match self {
+            CommandError::Exit(status) => status.code(),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandError::Spawn(err) => write!(f, "failed to spawn process: {}", err),
+            CommandError::Wait(err) => write!(f, "failed to wait for process: {}", err),
+            CommandError::Exit(status) => write!(f, "process exited with {}", status),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+/// Future resolving when a spawned child process exits.
+///
+/// Dropping it before the process exited kills the process.
+pub struct Exit {
+    child: Result<Child, Option<io::Error>>,
+    clock: Clock,
+    delay: Option<Sleep>,
+}
+
+impl Exit {
+    fn spawn(mut cmd: Command, clock: Clock) -> Self {
+        Exit {
+            child: cmd.spawn().map_err(Some),
+            clock,
+            delay: None,
+        }
+    }
+}
+
+impl Future for Exit {
+    type Output = Result<ExitStatus, CommandError>;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        loop {
+            let child = match self.child.as_mut() {
+                Ok(child) => child,
+                Err(err) => {
+                    let err = err.take().expect("polled after completion");
+                    return Poll::Ready(Err(CommandError::Spawn(err)));
+                }
+            };
+            match child.try_wait() {
+                Ok(Some(status)) if status.success() => return Poll::Ready(Ok(status)),
+                Ok(Some(status)) => return Poll::Ready(Err(CommandError::Exit(status))),
+                Ok(None) => {}
+                Err(err) => return Poll::Ready(Err(CommandError::Wait(err))),
+            }
+            let Exit { clock, delay, .. } = &mut *self;
+            let sleep = delay.get_or_insert_with(|| clock.sleep(POLL_INTERVAL));
+            match Pin::new(sleep).poll(ctx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(()) => *delay = None,
+            }
+        }
+    }
+}
+
+impl Drop for Exit {
+    fn drop(&mut self) {
+        if let Ok(child) = &mut self.child {
+            if let Ok(None) = child.try_wait() {
+                let _ = child.kill();
+                // Reap the killed process without blocking the executor, which may be a
+                // moment after the signal was delivered.
+                if let Ok(None) = child.try_wait() {
+                    if let Ok(mut child) = std::mem::replace(&mut self.child, Err(None)) {
+                        thread::spawn(move || child.wait());
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::{block_on, instant, Sleeper};
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    };
+
+    #[test]
+    fn test_retry_command() {
+        let res = block_on(retry_command(|| Command::new("true"), instant()));
+        assert!(res.unwrap().success());
+
+        let res = block_on(retry_command(
+            || {
+                let mut cmd = Command::new("sh");
+                cmd.args(["-c", "exit 3"]);
+                cmd
+            },
+            instant().num_attempts(2),
+        ));
+        assert!(res.is_err());
+
+        let res = block_on(
+            retry_command(
+                || {
+                    let mut cmd = Command::new("sh");
+                    cmd.args(["-c", "exit 3"]);
+                    cmd
+                },
+                instant().num_attempts(2),
+            )
+            .permanent_codes([2, 3]),
+        );
+        assert_eq!(res.unwrap_err().attempts(), 1);
+    }
+
+    #[test]
+    fn test_polls_on_retry_clock() {
+        struct Counting(Arc<AtomicU32>);
+
+        impl Sleeper for Counting {
+            fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Box::pin(futures_timer::Delay::new(duration))
+            }
+        }
+
+        let sleeps = Arc::new(AtomicU32::new(0));
+        let res = block_on(
+            retry_command(
+                || {
+                    let mut cmd = Command::new("sleep");
+                    cmd.arg("0.05");
+                    cmd
+                },
+                instant(),
+            )
+            .sleeper(Counting(sleeps.clone())),
+        );
+        assert!(res.unwrap().success());
+        assert!(sleeps.load(Ordering::SeqCst) > 0);
+    }
+
+    #[test]
+    fn test_drop_kills_child() {
+        let exit = Exit::spawn(
+            {
+                let mut cmd = Command::new("sleep");
+                cmd.arg("10");
+                cmd
+            },
+            Clock::System,
+        );
+        let id = exit.child.as_ref().unwrap().id().to_string();
+        drop(exit);
+        // The killed process is reaped in the background.
+        let reaped = (0..100).any(|_| {
+            let alive = Command::new("kill").args(["-0", &id]).status().unwrap();
+            if alive.success() {
+                thread::sleep(Duration::from_millis(10));
+            }
+            !alive.success()
+        });
+        assert!(reaped);
+    }
+}
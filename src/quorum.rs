@@ -0,0 +1,143 @@
+use crate::{retry, Backoff, BoxRetry, Retry, RetryError, Retryable};
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Retry a task on every replica until `k` of them succeeded.
+///
+/// Every replica is retried concurrently with its own backoff made by `policy`, so only the
+/// replicas which failed are retried while the others are done. Once `k` replicas succeeded
+/// the stragglers are dropped and the results are returned with the index of their replica,
+/// in the order they completed. As soon as too many replicas gave up to reach `k`, resolves to
+/// the error of the replica which gave up last. Covers quorum writes and multi-region reads.
+pub fn retry_quorum<I, R, F, S>(replicas: I, k: usize, policy: F) -> RetryQuorum<R::Item, R::Error>
+where
+    I: IntoIterator<Item = R>,
+    R: Retryable,
+    F: Fn() -> S,
+    S: Backoff + 'static,
+    Retry<R>: Send + 'static,
+{
+    let replicas: Vec<_> = replicas
+        .into_iter()
+        .map(|task| Some(retry(task, policy()).boxed()))
+        .collect();
+    assert!(k > 0, "k must be larger than zero");
+    assert!(
+        k <= replicas.len(),
+        "k must not be larger than the number of replicas"
+    );
+    RetryQuorum {
+        replicas,
+        k,
+        failed: 0,
+        results: Vec::new(),
+    }
+}
+
+/// Future returned by `retry_quorum`
+pub struct RetryQuorum<T, E> {
+    replicas: Vec<Option<BoxRetry<T, E>>>,
+    k: usize,
+    failed: usize,
+    results: Vec<(usize, T)>,
+}
+
+// The retries are boxed, the results are never pinned.
+impl<T, E> Unpin for RetryQuorum<T, E> {}
+
+impl<T, E> Future for RetryQuorum<T, E> {
+    type Output = Result<Vec<(usize, T)>, RetryError<E>>;
+
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut last_err = None;
+        for (idx, slot) in this.replicas.iter_mut().enumerate() {
+            let res = match slot {
+                Some(replica) => replica.as_mut().poll(ctx),
+                None => continue,
+            };
+            match res {
+                Poll::Ready(Ok(item)) => {
+                    *slot = None;
+                    this.results.push((idx, item));
+                }
+                Poll::Ready(Err(err)) => {
+                    *slot = None;
+                    this.failed += 1;
+                    last_err = Some(err);
+                }
+                Poll::Pending => {}
+            }
+        }
+        if this.results.len() >= this.k {
+            this.replicas.clear();
+            Poll::Ready(Ok(std::mem::take(&mut this.results)))
+        } else {
+            match last_err {
+                Some(err) if this.replicas.len() - this.failed < this.k => {
+                    this.replicas.clear();
+                    Poll::Ready(Err(err))
+                }
+                _ => Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block_on, instant};
+    use futures_timer::Delay;
+    use std::{
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    fn replica(
+        fails: u32,
+        delay: Duration,
+        calls: Arc<AtomicU32>,
+    ) -> impl Fn() -> Pin<Box<dyn Future<Output = Result<u32, ()>> + Send>> + Send + 'static {
+        move || {
+            let call = calls.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move {
+                Delay::new(delay).await;
+                if call < fails {
+                    Err(())
+                } else {
+                    Ok(call)
+                }
+            })
+        }
+    }
+
+    #[test]
+    fn test_retry_quorum() {
+        let calls: Vec<_> = (0..3).map(|_| Arc::new(AtomicU32::new(0))).collect();
+        let ms = Duration::from_millis;
+        let replicas = vec![
+            replica(0, ms(10), calls[0].clone()),
+            replica(2, ms(10), calls[1].clone()),
+            replica(0, ms(10_000), calls[2].clone()),
+        ];
+        let res = block_on(retry_quorum(replicas, 2, || instant().num_attempts(5)));
+        assert_eq!(res.unwrap(), vec![(0, 0), (1, 2)]);
+        let calls: Vec<_> = calls.iter().map(|c| c.load(Ordering::SeqCst)).collect();
+        assert_eq!(calls, vec![1, 3, 1]);
+
+        let replicas = vec![
+            replica(0, ms(10), Arc::new(AtomicU32::new(0))),
+            replica(5, ms(0), Arc::new(AtomicU32::new(0))),
+            replica(5, ms(0), Arc::new(AtomicU32::new(0))),
+        ];
+        let res = block_on(retry_quorum(replicas, 2, || instant().num_attempts(2)));
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{BoxRetry, RetryError};
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Run several retries concurrently and resolve with the first success.
+///
+/// Every retry runs its own loop with its own backoff, like racing IPv4 against IPv6 or two
+/// mirrors. The remaining retries are dropped as soon as one succeeds. Resolves to the error
+/// of the retry which gave up last when every retry gave up.
+///
+/// # Panics
+///
+/// Panics when `retries` is empty.
+pub fn retry_race<T, E, I>(retries: I) -> RetryRace<T, E>
+where
+    I: IntoIterator<Item = BoxRetry<T, E>>,
+{
+    let retries: Vec<_> = retries.into_iter().map(Some).collect();
+    assert!(!retries.is_empty(), "retries must not be empty");
+    RetryRace { retries }
+}
+
+/// Future returned by `retry_race`
+pub struct RetryRace<T, E> {
+    retries: Vec<Option<BoxRetry<T, E>>>,
+}
+
+impl<T, E> Future for RetryRace<T, E> {
+    type Output = Result<T, RetryError<E>>;
+
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut last_err = None;
+        for slot in this.retries.iter_mut() {
+            let res = match slot {
+                Some(retry) => retry.as_mut().poll(ctx),
+                None => continue,
+            };
+            match res {
+                Poll::Ready(Ok(item)) => {
+                    this.retries.clear();
+                    return Poll::Ready(Ok(item));
+                }
+                Poll::Ready(Err(err)) => {
+                    *slot = None;
+                    last_err = Some(err);
+                }
+                Poll::Pending => {}
+            }
+        }
+        match last_err {
+            Some(err) if this.retries.iter().all(Option::is_none) => Poll::Ready(Err(err)),
+            _ => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block_on, constant, instant, retry, Backoff};
+    use futures_timer::Delay;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_retry_race() {
+        let start = Instant::now();
+        let fut = retry_race(vec![
+            retry(
+                || async {
+                    Delay::new(Duration::from_secs(10)).await;
+                    Ok::<_, ()>("slow mirror")
+                },
+                instant(),
+            )
+            .boxed(),
+            retry(|| async { Err::<&str, _>(()) }, instant().num_attempts(2)).boxed(),
+            retry(
+                || async {
+                    Delay::new(Duration::from_millis(10)).await;
+                    Ok::<_, ()>("fast mirror")
+                },
+                constant(Duration::from_secs(1)),
+            )
+            .boxed(),
+        ]);
+        assert_eq!(block_on(fut).unwrap(), "fast mirror");
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        let fut = retry_race(vec![
+            retry(|| async { Err::<(), _>(()) }, instant().num_attempts(2)).boxed(),
+            retry(|| async { Err::<(), _>(()) }, instant().num_attempts(3)).boxed(),
+        ]);
+        assert_eq!(block_on(fut).unwrap_err().attempts(), 3);
+    }
+}
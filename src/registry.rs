@@ -0,0 +1,146 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+static REGISTRY: Mutex<BTreeMap<u64, (RetryInfo, Arc<PollCounters>)>> = Mutex::new(BTreeMap::new());
+
+/// Polls arriving this soon after the previous poll returned pending count as immediate.
+const IMMEDIATE_REPOLL: Duration = Duration::from_micros(100);
+
+/// What a registered `Retry` is currently doing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetryStatus {
+    /// The first attempt hasn't started yet.
+    Pending,
+    /// An attempt is in progress.
+    Trying,
+    /// Waiting for the next attempt.
+    Waiting,
+}
+
+/// The state of a registered `Retry` at the time of the snapshot.
+#[derive(Clone, Debug)]
+pub struct RetryInfo {
+    /// The name the `Retry` was registered with.
+    pub name: String,
+    /// The labels of the task, see `Retryable::metric_labels`.
+    pub labels: Vec<(String, String)>,
+    /// The current attempt number, zero before the first attempt.
+    pub attempt: u32,
+    /// What the `Retry` is currently doing.
+    pub status: RetryStatus,
+    /// When the next attempt is due while waiting.
+    pub next_wake: Option<Instant>,
+    /// How the `Retry` has been polled so far.
+    pub polls: PollStats,
+}
+
+/// Poll counters of a registered `Retry`, to diagnose busy loops and misbehaving wakers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PollStats {
+    /// Times the `Retry` was polled.
+    pub polls: u64,
+    /// Polls while waiting for the next attempt that found the wait not over yet.
+    pub spurious_wakeups: u64,
+    /// Polls arriving within 100µs of the previous poll returning pending.
+    pub immediate_repolls: u64,
+}
+
+#[derive(Debug, Default)]
+struct PollCounters {
+    polls: AtomicU64,
+    spurious_wakeups: AtomicU64,
+    immediate_repolls: AtomicU64,
+}
+
+/// Take a snapshot of all registered retries.
+///
+/// Retries are registered with `Retry::register` and are removed from the registry when they
+/// are dropped.
+pub fn snapshot() -> Vec<RetryInfo> {
+    let registry = REGISTRY.lock().unwrap();
+    registry
+        .values()
+        .map(|(info, counters)| RetryInfo {
+            polls: PollStats {
+                polls: counters.polls.load(Ordering::Relaxed),
+                spurious_wakeups: counters.spurious_wakeups.load(Ordering::Relaxed),
+                immediate_repolls: counters.immediate_repolls.load(Ordering::Relaxed),
+            },
+            ..info.clone()
+        })
+        .collect()
+}
+
+pub(crate) struct Registration {
+    id: u64,
+    counters: Arc<PollCounters>,
+    last_pending: Option<Instant>,
+}
+
+impl Registration {
+    pub(crate) fn new(name: String, labels: Vec<(String, String)>) -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let info = RetryInfo {
+            name,
+            labels,
+            attempt: 0,
+            status: RetryStatus::Pending,
+            next_wake: None,
+            polls: PollStats::default(),
+        };
+        let counters = Arc::new(PollCounters::default());
+        REGISTRY
+            .lock()
+            .unwrap()
+            .insert(id, (info, counters.clone()));
+        Registration {
+            id,
+            counters,
+            last_pending: None,
+        }
+    }
+
+    pub(crate) fn update(&self, attempt: u32, status: RetryStatus, next_wake: Option<Instant>) {
+        if let Some((info, _)) = REGISTRY.lock().unwrap().get_mut(&self.id) {
+            info.attempt = attempt;
+            info.status = status;
+            info.next_wake = next_wake;
+        }
+    }
+}
+
+impl Registration {
+    pub(crate) fn poll_started(&mut self) {
+        self.counters.polls.fetch_add(1, Ordering::Relaxed);
+        if let Some(last_pending) = self.last_pending.take() {
+            if last_pending.elapsed() < IMMEDIATE_REPOLL {
+                self.counters
+                    .immediate_repolls
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub(crate) fn poll_pending(&mut self) {
+        self.last_pending = Some(Instant::now());
+    }
+
+    pub(crate) fn spurious_wakeup(&self) {
+        self.counters
+            .spurious_wakeups
+            .fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        REGISTRY.lock().unwrap().remove(&self.id);
+    }
+}
@@ -0,0 +1,66 @@
+use crate::{Failures, RetryError};
+use std::fmt;
+
+impl<E> RetryError<Failures<E>>
+where
+    E: fmt::Display,
+{
+    /// Convert the error of a `retry_annotated` task into an `eyre::Report`.
+    ///
+    /// Every failed attempt becomes a cause of the report, the latest first, so the report
+    /// shows what each attempt tried without assembling it by hand.
+    pub fn into_report(self) -> eyre::Report {
+        let outcome = match &self {
+            RetryError::Exhausted { .. } => "gave up",
+            RetryError::TimedOut { .. } => return eyre::Report::msg(self.to_string()),
+            RetryError::Aborted { .. } => "aborted",
+            RetryError::Cancelled { .. } => "cancelled",
+        };
+        let summary = format!("{} after {} attempts", outcome, self.attempts());
+        let failures = match self.into_last_error() {
+            Some(failures) => failures.failures,
+            None => return eyre::Report::msg(summary),
+        };
+        let mut report: Option<eyre::Report> = None;
+        for failure in failures {
+            let failure = failure.to_string();
+            report = Some(match report {
+                Some(report) => report.wrap_err(failure),
+                None => eyre::Report::msg(failure),
+            });
+        }
+        match report {
+            Some(report) => report.wrap_err(summary),
+            None => eyre::Report::msg(summary),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{block_on, instant, retry_annotated, Attempt, Backoff, Notes};
+
+    #[test]
+    fn test_into_report() {
+        let res = block_on(retry_annotated(
+            |attempt: &Attempt, notes: Notes| {
+                let number = attempt.number;
+                async move {
+                    notes.annotate(format!("endpoint=eu-{}", number));
+                    Err::<(), _>("refused")
+                }
+            },
+            instant().num_attempts(2),
+        ));
+        let report = res.unwrap_err().into_report();
+        let chain: Vec<String> = report.chain().map(|cause| cause.to_string()).collect();
+        assert_eq!(
+            chain,
+            vec![
+                "gave up after 2 attempts",
+                "attempt 2 [endpoint=eu-2]: refused",
+                "attempt 1 [endpoint=eu-1]: refused",
+            ]
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use crate::{default_policy, defaults::MakeBackoff, retry, Backoff, Retry, Retryable};
+use std::collections::HashMap;
+
+type Route<M> = (String, Box<dyn Fn(&M) -> bool + Send + Sync>);
+
+/// Picks a named policy for every request based on its metadata.
+///
+/// Policies are registered by name with `policy` and selected by `route`, which matches the
+/// metadata passed by the caller, like the kind of operation, the tier of the tenant or the
+/// priority of the request. The first matching route wins, requests matching no route use
+/// the fallback. One router serves every request class without branching at call sites.
+pub struct PolicyRouter<M> {
+    policies: HashMap<String, MakeBackoff>,
+    routes: Vec<Route<M>>,
+    fallback: Option<MakeBackoff>,
+}
+
+impl<M> PolicyRouter<M> {
+    pub fn new() -> Self {
+        PolicyRouter {
+            policies: HashMap::new(),
+            routes: Vec::new(),
+            fallback: None,
+        }
+    }
+
+    /// Register the policy `name`, made by `factory` for every request.
+    pub fn policy<F, S>(mut self, name: impl Into<String>, factory: F) -> Self
+    where
+        F: Fn() -> S + Send + Sync + 'static,
+        S: Backoff + 'static,
+    {
+        let factory: MakeBackoff = Box::new(move || Box::new(factory()));
+        self.policies.insert(name.into(), factory);
+        self
+    }
+
+    /// Use the policy `name` for requests matching `matches`.
+    pub fn route<F>(mut self, name: impl Into<String>, matches: F) -> Self
+    where
+        F: Fn(&M) -> bool + Send + Sync + 'static,
+    {
+        let name = name.into();
+        assert!(
+            self.policies.contains_key(&name),
+            "route to unknown policy `{}`",
+            name
+        );
+        self.routes.push((name, Box::new(matches)));
+        self
+    }
+
+    /// Use the backoff made by `factory` for requests matching no route.
+    ///
+    /// Defaults to `default_policy`.
+    pub fn fallback<F, S>(mut self, factory: F) -> Self
+    where
+        F: Fn() -> S + Send + Sync + 'static,
+        S: Backoff + 'static,
+    {
+        self.fallback = Some(Box::new(move || Box::new(factory())));
+        self
+    }
+
+    /// The name of the policy selected for `meta`, `None` if the fallback is used.
+    pub fn select(&self, meta: &M) -> Option<&str> {
+        self.routes
+            .iter()
+            .find(|(_, matches)| matches(meta))
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Make the backoff for a request with the metadata `meta`.
+    pub fn backoff(&self, meta: &M) -> Box<dyn Backoff> {
+        match self.select(meta) {
+            Some(name) => self.policies[name](),
+            None => match &self.fallback {
+                Some(factory) => factory(),
+                None => default_policy(),
+            },
+        }
+    }
+
+    /// Retry `task` with the policy selected for `meta`.
+    pub fn retry<R>(&self, task: R, meta: &M) -> Retry<R>
+    where
+        R: Retryable,
+    {
+        retry(task, self.backoff(meta))
+    }
+}
+
+impl<M> Default for PolicyRouter<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{constant, instant};
+    use std::time::Duration;
+
+    #[derive(PartialEq)]
+    enum Tier {
+        Free,
+        Paid,
+    }
+
+    struct Request {
+        tier: Tier,
+        interactive: bool,
+    }
+
+    #[test]
+    fn test_policy_router() {
+        let router = PolicyRouter::new()
+            .policy("interactive", || instant().num_attempts(2))
+            .policy("bulk", || constant(Duration::from_secs(5)))
+            .route("interactive", |req: &Request| req.interactive)
+            .route("bulk", |req: &Request| req.tier == Tier::Free)
+            .fallback(|| constant(Duration::from_secs(1)));
+
+        let req = Request {
+            tier: Tier::Free,
+            interactive: true,
+        };
+        assert_eq!(router.select(&req), Some("interactive"));
+        let mut bo = router.backoff(&req);
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(0)));
+        assert_eq!(bo.next_retry(), None);
+
+        let req = Request {
+            tier: Tier::Free,
+            interactive: false,
+        };
+        assert_eq!(router.select(&req), Some("bulk"));
+        assert_eq!(
+            router.backoff(&req).next_retry(),
+            Some(Duration::from_secs(5))
+        );
+
+        let req = Request {
+            tier: Tier::Paid,
+            interactive: false,
+        };
+        assert_eq!(router.select(&req), None);
+        assert_eq!(
+            router.backoff(&req).next_retry(),
+            Some(Duration::from_secs(1))
+        );
+    }
+}
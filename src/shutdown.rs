@@ -0,0 +1,177 @@
+use crate::{constant, retry, Backoff, Retryable};
+use futures_timer::Delay;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+type Cleanup = Pin<Box<dyn Future<Output = bool> + Send>>;
+
+/// Runs critical cleanup tasks during graceful shutdown.
+///
+/// Tasks like flushing buffers, deregistering from service discovery or releasing locks are
+/// collected with `add` and run concurrently by `run`, each with short bounded retries. The
+/// whole shutdown is bounded by an overall deadline, tasks still running when it passes are
+/// dropped and reported as timed out.
+pub struct ShutdownRetrier {
+    deadline: Duration,
+    tasks: Vec<(String, Cleanup)>,
+}
+
+impl ShutdownRetrier {
+    /// Create a retrier which gives all cleanup tasks together at most `deadline` to finish.
+    pub fn new(deadline: Duration) -> Self {
+        ShutdownRetrier {
+            deadline,
+            tasks: Vec::new(),
+        }
+    }
+
+    /// Add a cleanup task, retried 3 times with 100ms in between.
+    pub fn add<R>(&mut self, name: impl Into<String>, task: R) -> &mut Self
+    where
+        R: Retryable + Send + 'static,
+        R::Future: Send,
+        R::Item: Send,
+        R::Error: Send,
+    {
+        let backoff = constant(Duration::from_millis(100)).num_attempts(3);
+        self.add_with_backoff(name, task, backoff)
+    }
+
+    /// Add a cleanup task, retried according to `backoff`.
+    pub fn add_with_backoff<R, S>(
+        &mut self,
+        name: impl Into<String>,
+        task: R,
+        backoff: S,
+    ) -> &mut Self
+    where
+        R: Retryable + Send + 'static,
+        R::Future: Send,
+        R::Item: Send,
+        R::Error: Send,
+        S: Backoff + Send + 'static,
+    {
+        let fut = retry(task, backoff);
+        self.tasks
+            .push((name.into(), Box::pin(async move { fut.await.is_ok() })));
+        self
+    }
+
+    /// Run all cleanup tasks concurrently until they finished or the deadline passed.
+    pub fn run(self) -> Shutdown {
+        Shutdown {
+            deadline: Delay::new(self.deadline),
+            tasks: self.tasks.into_iter().map(Some).collect(),
+            report: ShutdownReport::default(),
+        }
+    }
+}
+
+/// The outcome of the cleanup tasks of a `ShutdownRetrier`, by name.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// Tasks which succeeded.
+    pub completed: Vec<String>,
+    /// Tasks which gave up after exhausting their retries.
+    pub failed: Vec<String>,
+    /// Tasks which were still running when the deadline passed.
+    pub timed_out: Vec<String>,
+}
+
+impl ShutdownReport {
+    /// Whether all cleanup tasks succeeded.
+    pub fn is_clean(&self) -> bool {
+        self.failed.is_empty() && self.timed_out.is_empty()
+    }
+}
+
+/// Future returned by `ShutdownRetrier::run`
+pub struct Shutdown {
+    deadline: Delay,
+    tasks: Vec<Option<(String, Cleanup)>>,
+    report: ShutdownReport,
+}
+
+impl Future for Shutdown {
+    type Output = ShutdownReport;
+
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        for slot in this.tasks.iter_mut() {
+            let done = match slot {
+                Some((_, task)) => task.as_mut().poll(ctx),
+                None => continue,
+            };
+            if let Poll::Ready(ok) = done {
+                let (name, _) = slot.take().unwrap();
+                if ok {
+                    this.report.completed.push(name);
+                } else {
+                    tracing::error!(task = %name, "cleanup task failed");
+                    this.report.failed.push(name);
+                }
+            }
+        }
+
+        if this.tasks.iter().all(Option::is_none) {
+            return Poll::Ready(std::mem::take(&mut this.report));
+        }
+        if Pin::new(&mut this.deadline).poll(ctx).is_pending() {
+            return Poll::Pending;
+        }
+        for (name, _) in this.tasks.drain(..).flatten() {
+            tracing::error!(task = %name, "cleanup task timed out");
+            this.report.timed_out.push(name);
+        }
+        Poll::Ready(std::mem::take(&mut this.report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block_on, instant};
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    };
+
+    #[test]
+    fn test_shutdown_retrier() {
+        let flushes = Arc::new(AtomicU32::new(0));
+        let counter = flushes.clone();
+
+        let mut shutdown = ShutdownRetrier::new(Duration::from_millis(100));
+        shutdown
+            .add("flush", move || {
+                let flush = counter.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    if flush == 0 {
+                        Err(())
+                    } else {
+                        Ok(())
+                    }
+                }
+            })
+            .add_with_backoff(
+                "deregister",
+                || async { Err::<(), _>(()) },
+                instant().num_attempts(2),
+            )
+            .add("release lock", std::future::pending::<Result<(), ()>>);
+
+        let report = block_on(shutdown.run());
+        assert_eq!(report.completed, vec!["flush"]);
+        assert_eq!(report.failed, vec!["deregister"]);
+        assert_eq!(report.timed_out, vec!["release lock"]);
+        assert!(!report.is_clean());
+        assert_eq!(flushes.load(Ordering::SeqCst), 2);
+
+        let report = block_on(ShutdownRetrier::new(Duration::from_secs(1)).run());
+        assert!(report.is_clean());
+    }
+}
@@ -0,0 +1,172 @@
+use crate::{
+    executor, AttemptContext, Control, ManualClock, Middleware, Retry, RetryError, Retryable,
+};
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    thread,
+    time::Duration,
+};
+
+/// Runs a `Retry` end to end in virtual time.
+///
+/// The retry is driven by a `ManualClock` which jumps straight to the next due wait whenever
+/// the retry is idle, so an entire policy runs in microseconds however long its delays are.
+/// Combined with scripted failures from `FailureInjector` and seeded jitter, the produced
+/// schedule is deterministic and can be asserted on. Deadlines follow the virtual clock too,
+/// set them relative to `ManualClock::now` of `Simulation::clock`.
+#[derive(Clone, Debug, Default)]
+pub struct Simulation {
+    clock: ManualClock,
+}
+
+impl Simulation {
+    pub fn new() -> Self {
+        Simulation {
+            clock: ManualClock::new(),
+        }
+    }
+
+    /// The virtual clock of the simulation.
+    ///
+    /// Pass it to `FailureInjector::manual_clock` to have injected latency pass in virtual
+    /// time as well.
+    pub fn clock(&self) -> ManualClock {
+        self.clock.clone()
+    }
+
+    /// Run `retry` to completion and report when each attempt was started.
+    ///
+    /// Tasks should complete immediately or wait on the simulation's clock, waits on anything
+    /// else block the thread like a regular executor would.
+    pub fn run<R>(&self, retry: Retry<R>) -> SimulationReport<R::Item, R::Error>
+    where
+        R: Retryable,
+    {
+        let clock = self.clock.clone();
+        let start = clock.elapsed();
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let recorder = Recorder {
+            clock: clock.clone(),
+            start,
+            attempts: attempts.clone(),
+        };
+        let mut fut = Box::pin(retry.manual_clock(clock.clone()).middleware(recorder));
+
+        let waker = executor::thread_waker();
+        let mut ctx = Context::from_waker(&waker);
+        let result = loop {
+            if let Poll::Ready(result) = fut.as_mut().poll(&mut ctx) {
+                break result;
+            }
+            match clock.next_deadline() {
+                Some(deadline) => clock.advance(deadline.saturating_sub(clock.elapsed())),
+                None => thread::park(),
+            }
+        };
+
+        let attempts = attempts.lock().unwrap().clone();
+        SimulationReport {
+            result,
+            attempts,
+            elapsed: clock.elapsed() - start,
+        }
+    }
+}
+
+/// The outcome of a `Simulation`.
+#[derive(Clone, Debug)]
+pub struct SimulationReport<T, E> {
+    /// The result of the retry.
+    pub result: Result<T, RetryError<E>>,
+    /// The virtual time at which each attempt was started.
+    pub attempts: Vec<Duration>,
+    /// The virtual time the whole retry took.
+    pub elapsed: Duration,
+}
+
+impl<T, E> SimulationReport<T, E> {
+    /// The time between the starts of consecutive attempts.
+    pub fn delays(&self) -> Vec<Duration> {
+        self.attempts.windows(2).map(|w| w[1] - w[0]).collect()
+    }
+}
+
+struct Recorder {
+    clock: ManualClock,
+    /// The time of the clock when the run started.
+    start: Duration,
+    attempts: Arc<Mutex<Vec<Duration>>>,
+}
+
+impl<T, E> Middleware<T, E> for Recorder {
+    fn before_attempt(&mut self, _context: &mut AttemptContext) -> Control {
+        let now = self.clock.elapsed() - self.start;
+        self.attempts.lock().unwrap().push(now);
+        Control::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{constant, retry, Backoff, FailureInjector};
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_simulation() {
+        let task = FailureInjector::new(|| async { Ok::<_, &str>("done") }, || "injected")
+            .script(vec![true, true, true]);
+        let report = Simulation::new().run(retry(task, constant(secs(10)).exponential()));
+        assert_eq!(report.result.as_ref().unwrap(), &"done");
+        assert_eq!(report.delays(), vec![secs(10), secs(20), secs(40)]);
+        assert_eq!(report.elapsed, secs(70));
+
+        let task =
+            FailureInjector::new(|| async { Ok::<_, &str>("done") }, || "injected").every_nth(1);
+        let simulation = Simulation::new();
+        let deadline = simulation.clock().now() + secs(60);
+        let report = simulation.run(retry(
+            task,
+            constant(secs(10)).exponential().deadline(deadline),
+        ));
+        assert_eq!(
+            report.result,
+            Err(RetryError::Exhausted {
+                attempts: 3,
+                error: "injected"
+            })
+        );
+        assert_eq!(report.attempts, vec![secs(0), secs(10), secs(30)]);
+        assert_eq!(report.elapsed, secs(60));
+
+        let report = Simulation::new().run(retry(
+            || async { Err::<(), _>(()) },
+            constant(secs(60)).jitter(0.5).seed(7).num_attempts(4),
+        ));
+        let again = Simulation::new().run(retry(
+            || async { Err::<(), _>(()) },
+            constant(secs(60)).jitter(0.5).seed(7).num_attempts(4),
+        ));
+        assert_eq!(report.attempts.len(), 4);
+        assert_eq!(report.delays(), again.delays());
+
+        let simulation = Simulation::new();
+        let task = FailureInjector::new(|| async { Ok::<_, &str>("done") }, || "injected")
+            .script(vec![true])
+            .latency(secs(5))
+            .manual_clock(simulation.clock());
+        let report = simulation.run(retry(task, constant(secs(10))));
+        assert_eq!(report.attempts, vec![secs(0), secs(15)]);
+        assert_eq!(report.elapsed, secs(20));
+        let report = simulation.run(retry(
+            || async { Err::<(), _>(()) },
+            constant(secs(10)).num_attempts(2),
+        ));
+        assert_eq!(report.attempts, vec![secs(0), secs(10)]);
+    }
+}
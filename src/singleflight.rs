@@ -0,0 +1,247 @@
+use crate::{BoxRetry, Retry, RetryError, Retryable};
+use std::{
+    collections::HashMap,
+    future::Future,
+    hash::Hash,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Wake, Waker},
+};
+
+/// Coalesces concurrent retries of the same resource into one.
+///
+/// While a retry for a key is in flight, further calls with the same key don't start a retry of
+/// their own but wait for the one in flight and receive a clone of its result. Once it finished
+/// the next call for that key starts a new retry.
+pub struct Singleflight<K, T, E> {
+    flights: Flights<K, T, E>,
+}
+
+impl<K, T, E> Singleflight<K, T, E>
+where
+    K: Hash + Eq + Clone,
+    T: Clone,
+    E: Clone,
+{
+    pub fn new() -> Self {
+        Singleflight {
+            flights: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Join the retry in flight for `key`, or start one with `start` if there is none.
+    pub fn retry<R, F>(&self, key: K, start: F) -> Coalesced<K, T, E>
+    where
+        R: Retryable<Item = T, Error = E>,
+        E: std::fmt::Debug,
+        F: FnOnce() -> Retry<R>,
+        Retry<R>: Send + 'static,
+    {
+        self.join(key, || start().boxed())
+    }
+
+    /// Join the flight for `key`, or start one with the boxed retry made by `start`.
+    pub(crate) fn join<F>(&self, key: K, start: F) -> Coalesced<K, T, E>
+    where
+        F: FnOnce() -> BoxRetry<T, E>,
+    {
+        let mut flights = self.flights.lock().unwrap();
+        let flight = flights
+            .entry(key.clone())
+            .or_insert_with(|| {
+                Arc::new(Flight {
+                    state: Mutex::new(FlightState {
+                        fut: Some(start()),
+                        result: None,
+                        waiters: 0,
+                    }),
+                    wakers: Arc::new(Wakers(Mutex::new(Vec::new()))),
+                })
+            })
+            .clone();
+        flight.state.lock().unwrap().waiters += 1;
+        Coalesced {
+            key,
+            flight,
+            flights: self.flights.clone(),
+        }
+    }
+
+    /// The number of retries currently in flight.
+    pub fn in_flight(&self) -> usize {
+        self.flights.lock().unwrap().len()
+    }
+}
+
+impl<K, T, E> Default for Singleflight<K, T, E>
+where
+    K: Hash + Eq + Clone,
+    T: Clone,
+    E: Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, T, E> Clone for Singleflight<K, T, E> {
+    fn clone(&self) -> Self {
+        Singleflight {
+            flights: self.flights.clone(),
+        }
+    }
+}
+
+type Flights<K, T, E> = Arc<Mutex<HashMap<K, Arc<Flight<T, E>>>>>;
+
+struct Flight<T, E> {
+    state: Mutex<FlightState<T, E>>,
+    wakers: Arc<Wakers>,
+}
+
+struct FlightState<T, E> {
+    fut: Option<BoxRetry<T, E>>,
+    result: Option<Result<T, RetryError<E>>>,
+    waiters: usize,
+}
+
+/// Wakes every task waiting on a flight, whichever of them polled the retry last.
+struct Wakers(Mutex<Vec<Waker>>);
+
+impl Wakers {
+    fn register(&self, waker: &Waker) {
+        let mut wakers = self.0.lock().unwrap();
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+}
+
+impl Wake for Wakers {
+    fn wake(self: Arc<Self>) {
+        let wakers = std::mem::take(&mut *self.0.lock().unwrap());
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+/// Future returned by `Singleflight::retry`
+pub struct Coalesced<K, T, E>
+where
+    K: Hash + Eq,
+{
+    key: K,
+    flight: Arc<Flight<T, E>>,
+    flights: Flights<K, T, E>,
+}
+
+// The key is never pinned, the retry itself is boxed.
+impl<K, T, E> Unpin for Coalesced<K, T, E> where K: Hash + Eq {}
+
+impl<K, T, E> Coalesced<K, T, E>
+where
+    K: Hash + Eq,
+{
+    /// Forget the flight if it's still the one registered for this key.
+    fn remove_flight(&self, flights: &mut HashMap<K, Arc<Flight<T, E>>>) {
+        if let Some(flight) = flights.get(&self.key) {
+            if Arc::ptr_eq(flight, &self.flight) {
+                flights.remove(&self.key);
+            }
+        }
+    }
+}
+
+impl<K, T, E> Future for Coalesced<K, T, E>
+where
+    K: Hash + Eq,
+    T: Clone,
+    E: Clone,
+{
+    type Output = Result<T, RetryError<E>>;
+
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut state = this.flight.state.lock().unwrap();
+        if let Some(result) = &state.result {
+            return Poll::Ready(result.clone());
+        }
+
+        this.flight.wakers.register(ctx.waker());
+        let waker = Waker::from(this.flight.wakers.clone());
+        let fut = state
+            .fut
+            .as_mut()
+            .expect("flight finished without a result");
+        let result = match fut.as_mut().poll(&mut Context::from_waker(&waker)) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        state.fut = None;
+        state.result = Some(result.clone());
+        drop(state);
+
+        this.remove_flight(&mut this.flights.lock().unwrap());
+        waker.wake();
+        Poll::Ready(result)
+    }
+}
+
+impl<K, T, E> Drop for Coalesced<K, T, E>
+where
+    K: Hash + Eq,
+{
+    fn drop(&mut self) {
+        let mut flights = self.flights.lock().unwrap();
+        let mut state = self.flight.state.lock().unwrap();
+        state.waiters -= 1;
+        if state.waiters == 0 && state.result.is_none() {
+            // Nobody is waiting for the retry anymore, so stop it instead of leaving it behind.
+            state.fut = None;
+            drop(state);
+            self.remove_flight(&mut flights);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block_on, instant, retry, Backoff};
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_singleflight() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let task = {
+            let calls = calls.clone();
+            move || {
+                let call = calls.fetch_add(1, Ordering::SeqCst) + 1;
+                async move {
+                    if call.is_multiple_of(3) {
+                        Ok(call)
+                    } else {
+                        Err(())
+                    }
+                }
+            }
+        };
+
+        let flights = Singleflight::new();
+        let a = flights.retry("user/1", || retry(task.clone(), instant().num_attempts(5)));
+        let b = flights.retry("user/1", || retry(task.clone(), instant().num_attempts(5)));
+        assert_eq!(flights.in_flight(), 1);
+        assert_eq!(block_on(a).unwrap(), 3);
+        assert_eq!(block_on(b).unwrap(), 3);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(flights.in_flight(), 0);
+
+        let c = flights.retry("user/1", || retry(task, instant().num_attempts(5)));
+        assert_eq!(block_on(c).unwrap(), 6);
+
+        let d = flights.retry("user/2", || retry(|| async { Ok::<u32, ()>(0) }, instant()));
+        drop(d);
+        assert_eq!(flights.in_flight(), 0);
+    }
+}
@@ -0,0 +1,157 @@
+use crate::{Attempt, AttemptContext, ErrorKind, Retryable};
+use pin_project::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tracing_error::SpanTrace;
+
+/// Capture a `SpanTrace` for every failed attempt of `task`.
+///
+/// Attempts fail with `Traced`, so the `RetryError` of a retry which gave up carries the span
+/// trace of its last failed attempt, showing where in the call graph the failure originated.
+/// Span traces are only captured when the subscriber has a `tracing_error::ErrorLayer`.
+pub fn trace_attempts<R>(task: R) -> TraceAttempts<R>
+where
+    R: Retryable,
+{
+    TraceAttempts { task }
+}
+
+/// Retryable returned by `trace_attempts`
+pub struct TraceAttempts<R> {
+    task: R,
+}
+
+impl<R> Retryable for TraceAttempts<R>
+where
+    R: Retryable,
+{
+    type Item = R::Item;
+    type Error = Traced<R::Error>;
+    type Future = TracedAttempt<R::Future>;
+
+    fn call(&self) -> Self::Future {
+        TracedAttempt {
+            fut: self.task.call(),
+        }
+    }
+
+    fn call_attempt(&self, attempt: &Attempt) -> Self::Future {
+        TracedAttempt {
+            fut: self.task.call_attempt(attempt),
+        }
+    }
+
+    fn call_with_context(&self, context: &AttemptContext) -> Self::Future {
+        TracedAttempt {
+            fut: self.task.call_with_context(context),
+        }
+    }
+
+    fn metric_labels(&self) -> &[(&str, String)] {
+        self.task.metric_labels()
+    }
+
+    fn classify(&self, error: &Self::Error) -> ErrorKind {
+        self.task.classify(&error.error)
+    }
+
+    fn report_error(&self, error: &Self::Error, next_retry: Option<Duration>) {
+        self.task.report_error(&error.error, next_retry)
+    }
+
+    fn report_attempt_error(
+        &self,
+        error: &Self::Error,
+        next_retry: Option<Duration>,
+        attempt: &Attempt,
+    ) {
+        self.task
+            .report_attempt_error(&error.error, next_retry, attempt)
+    }
+
+    fn report_timeout(&self, timeout: Duration, next_retry: Option<Duration>) {
+        self.task.report_timeout(timeout, next_retry)
+    }
+}
+
+/// The error of an attempt along with the span trace of where it failed.
+#[derive(Clone, Debug)]
+pub struct Traced<E> {
+    /// The error the attempt failed with.
+    pub error: E,
+    /// The spans the attempt was in when it failed.
+    pub span_trace: SpanTrace,
+}
+
+impl<E> fmt::Display for Traced<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\n{}", self.error, self.span_trace)
+    }
+}
+
+impl<E> std::error::Error for Traced<E>
+where
+    E: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Future returned by `TraceAttempts::call_attempt`
+#[pin_project]
+pub struct TracedAttempt<Fut> {
+    #[pin]
+    fut: Fut,
+}
+
+impl<Fut, I, E> Future for TracedAttempt<Fut>
+where
+    Fut: Future<Output = Result<I, E>>,
+{
+    type Output = Result<I, Traced<E>>;
+
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project().fut.poll(ctx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Ok(item)) => Poll::Ready(Ok(item)),
+            Poll::Ready(Err(error)) => Poll::Ready(Err(Traced {
+                error,
+                span_trace: SpanTrace::capture(),
+            })),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block_on, instant, retry, Backoff};
+    use tracing_error::ErrorLayer;
+    use tracing_subscriber::{layer::SubscriberExt, Registry};
+
+    #[test]
+    fn test_trace_attempts() {
+        let subscriber = Registry::default().with(ErrorLayer::default());
+        let res = tracing::subscriber::with_default(subscriber, || {
+            let task = trace_attempts(|| async { Err::<(), _>("refused") });
+            block_on(
+                retry(task, instant().num_attempts(2))
+                    .attempt_span(|attempt| tracing::info_span!("fetch", attempt)),
+            )
+        });
+        let err = res.unwrap_err();
+        let traced = err.last_error().unwrap();
+        assert_eq!(traced.error, "refused");
+        assert!(traced.span_trace.to_string().contains("fetch"));
+        assert!(traced.span_trace.to_string().contains("attempt=2"));
+    }
+}
@@ -0,0 +1,192 @@
+use crate::{constant, instant, Backoff};
+use std::{fmt, time::Duration};
+
+/// Error returned when a backoff spec can't be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    message: String,
+}
+
+impl ParseError {
+    fn new(message: impl Into<String>) -> Self {
+        ParseError {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl dyn Backoff {
+    /// Parse a backoff from a spec string, see `parse_backoff`.
+    pub fn parse(spec: &str) -> Result<Box<dyn Backoff>, ParseError> {
+        parse_backoff(spec)
+    }
+}
+
+/// Parse a backoff from a spec string like `exponential:100ms,x2,max=30s,jitter=0.2,attempts=8`.
+///
+/// The spec starts with the kind of backoff, `instant`, `constant:DELAY` or
+/// `exponential:DELAY`, followed by comma separated options:
+///
+/// - `xN` multiplies exponential delays by `N` every retry (defaults to 2),
+/// - `min=DELAY` and `max=DELAY` bound the delay,
+/// - `jitter=SCALE` randomizes the delay,
+/// - `attempts=N` caps the total number of attempts, including the first one,
+/// - `retries=N` caps the number of retries after the first attempt.
+///
+/// Delays are written like `250ms`, `1s`, `5m` or `1h`. Options are applied in the order
+/// listed above, regardless of their order in the spec.
+pub fn parse_backoff(spec: &str) -> Result<Box<dyn Backoff>, ParseError> {
+    let mut parts = spec.split(',').map(str::trim);
+    let head = parts.next().unwrap_or("");
+    let (kind, delay) = match head.find(':') {
+        Some(idx) => (&head[..idx], Some(parse_duration(&head[idx + 1..])?)),
+        None => (head, None),
+    };
+
+    let mut factor = None;
+    let mut min = None;
+    let mut max = None;
+    let mut jitter = None;
+    let mut attempts = None;
+    let mut retries = None;
+    for option in parts {
+        if let Some(num) = option.strip_prefix('x') {
+            factor = Some(parse_num::<u32>("factor", num, 1)?);
+            continue;
+        }
+        let (key, value) = match option.find('=') {
+            Some(idx) => (&option[..idx], &option[idx + 1..]),
+            None => return Err(ParseError::new(format!("unknown option `{}`", option))),
+        };
+        match key {
+            "min" => min = Some(parse_duration(value)?),
+            "max" => max = Some(parse_duration(value)?),
+            "jitter" => {
+                let scale: f64 = value
+                    .parse()
+                    .map_err(|_| ParseError::new(format!("invalid jitter `{}`", value)))?;
+                if scale <= 0.0 || scale > 1.0 {
+                    return Err(ParseError::new(
+                        "jitter must be larger than zero and at most one",
+                    ));
+                }
+                jitter = Some(scale);
+            }
+            "attempts" => attempts = Some(parse_num::<u32>("attempts", value, 1)?),
+            "retries" => retries = Some(parse_num::<u32>("retries", value, 0)?),
+            _ => return Err(ParseError::new(format!("unknown option `{}`", key))),
+        }
+    }
+
+    let mut backoff: Box<dyn Backoff> = match (kind, delay) {
+        ("instant", None) => Box::new(instant()),
+        ("constant", Some(delay)) | ("const", Some(delay)) => Box::new(constant(delay)),
+        ("exponential", Some(delay)) | ("exp", Some(delay)) => {
+            Box::new(constant(delay).exponential_by(factor.unwrap_or(2)))
+        }
+        ("instant", Some(_)) => return Err(ParseError::new("instant takes no delay")),
+        ("constant", None) | ("const", None) | ("exponential", None) | ("exp", None) => {
+            return Err(ParseError::new(format!("expected {}:DELAY", kind)))
+        }
+        _ => return Err(ParseError::new(format!("unknown kind `{}`", kind))),
+    };
+    if factor.is_some() && !matches!(kind, "exponential" | "exp") {
+        return Err(ParseError::new("factor requires an exponential backoff"));
+    }
+    if let Some(min) = min {
+        backoff = Box::new(backoff.min_backoff(min));
+    }
+    if let Some(max) = max {
+        backoff = Box::new(backoff.max_backoff(max));
+    }
+    if let Some(scale) = jitter {
+        backoff = Box::new(backoff.jitter(scale));
+    }
+    if let Some(num) = attempts {
+        backoff = Box::new(backoff.num_attempts(num));
+    }
+    if let Some(num) = retries {
+        backoff = Box::new(backoff.max_retries(num));
+    }
+    Ok(backoff)
+}
+
+fn parse_num<T>(name: &str, value: &str, min: T) -> Result<T, ParseError>
+where
+    T: std::str::FromStr + PartialOrd + fmt::Display,
+{
+    match value.parse::<T>() {
+        Ok(num) if num >= min => Ok(num),
+        Ok(_) => Err(ParseError::new(format!(
+            "{} must be at least {}",
+            name, min
+        ))),
+        Err(_) => Err(ParseError::new(format!("invalid {} `{}`", name, value))),
+    }
+}
+
+fn parse_duration(value: &str) -> Result<Duration, ParseError> {
+    let idx = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (num, unit) = value.split_at(idx);
+    let num: u64 = num
+        .parse()
+        .map_err(|_| ParseError::new(format!("invalid delay `{}`", value)))?;
+    match unit {
+        "us" => Ok(Duration::from_micros(num)),
+        "ms" => Ok(Duration::from_millis(num)),
+        "s" => Ok(Duration::from_secs(num)),
+        "m" => Ok(Duration::from_secs(num.saturating_mul(60))),
+        "h" => Ok(Duration::from_secs(num.saturating_mul(60 * 60))),
+        _ => Err(ParseError::new(format!(
+            "invalid delay unit in `{}`",
+            value
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_backoff() {
+        let mut bo = <dyn Backoff>::parse("exponential:100ms,x3,max=1s,attempts=4").unwrap();
+        assert_eq!(bo.next_retry(), Some(Duration::from_millis(100)));
+        assert_eq!(bo.next_retry(), Some(Duration::from_millis(300)));
+        assert_eq!(bo.next_retry(), Some(Duration::from_millis(900)));
+        assert_eq!(bo.next_retry(), None);
+
+        let mut bo = parse_backoff("constant:2s,jitter=0.5").unwrap();
+        let dur = bo.next_retry().unwrap();
+        assert!(dur >= Duration::from_secs(1) && dur <= Duration::from_secs(2));
+
+        let mut bo = parse_backoff("constant:1s,retries=1").unwrap();
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(1)));
+        assert_eq!(bo.next_retry(), None);
+
+        let mut bo = parse_backoff("instant").unwrap();
+        assert_eq!(bo.next_retry(), Some(Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn test_parse_backoff_errors() {
+        assert!(parse_backoff("").is_err());
+        assert!(parse_backoff("exponential").is_err());
+        assert!(parse_backoff("linear:1s").is_err());
+        assert!(parse_backoff("constant:1s,x2").is_err());
+        assert!(parse_backoff("constant:1 s").is_err());
+        assert!(parse_backoff("constant:1s,attempts=0").is_err());
+        assert!(parse_backoff("constant:1s,jitter=2").is_err());
+        assert!(parse_backoff("constant:1s,bogus=1").is_err());
+    }
+}
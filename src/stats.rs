@@ -0,0 +1,300 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+/// When the first tracked retry was polled.
+static STARTED: OnceLock<Instant> = OnceLock::new();
+
+/// Counters over all retries, kept apart from `NAMED` so unnamed retries never lock.
+static TOTAL: Totals = Totals {
+    in_progress: AtomicU64::new(0),
+    attempts: AtomicU64::new(0),
+    successes: AtomicU64::new(0),
+    exhaustions: AtomicU64::new(0),
+};
+
+static NAMED: Mutex<Named> = Mutex::new(Named {
+    by_name: BTreeMap::new(),
+    health: BTreeMap::new(),
+});
+
+/// Weight of the newest attempt in the moving averages of `Health`.
+const EWMA_ALPHA: f64 = 0.2;
+
+struct Totals {
+    in_progress: AtomicU64,
+    attempts: AtomicU64,
+    successes: AtomicU64,
+    exhaustions: AtomicU64,
+}
+
+impl Totals {
+    fn counter(&self, counter: Counter) -> &AtomicU64 {
+        match counter {
+            Counter::InProgress => &self.in_progress,
+            Counter::Attempts => &self.attempts,
+            Counter::Successes => &self.successes,
+            Counter::Exhaustions => &self.exhaustions,
+        }
+    }
+
+    fn load(&self) -> Counters {
+        Counters {
+            in_progress: self.in_progress.load(Ordering::Relaxed),
+            attempts: self.attempts.load(Ordering::Relaxed),
+            successes: self.successes.load(Ordering::Relaxed),
+            exhaustions: self.exhaustions.load(Ordering::Relaxed),
+        }
+    }
+}
+
+struct Named {
+    by_name: BTreeMap<String, Counters>,
+    health: BTreeMap<String, Health>,
+}
+
+#[derive(Clone, Copy)]
+enum Counter {
+    InProgress,
+    Attempts,
+    Successes,
+    Exhaustions,
+}
+
+/// Add one to (or with `decrement` take one from) `counter` of the totals and of the retries
+/// named `name`.
+fn update(name: Option<&str>, counter: Counter, decrement: bool) {
+    let total = TOTAL.counter(counter);
+    if decrement {
+        total.fetch_sub(1, Ordering::Relaxed);
+    } else {
+        total.fetch_add(1, Ordering::Relaxed);
+    }
+    if let Some(name) = name {
+        let mut named = NAMED.lock().unwrap();
+        let counters = named.by_name.entry(name.to_string()).or_default();
+        let value = counters.counter(counter);
+        *value = if decrement { *value - 1 } else { *value + 1 };
+    }
+}
+
+/// Aggregated counters of a set of retries.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Counters {
+    /// Retries which haven't succeeded or given up yet.
+    pub in_progress: u64,
+    /// Attempts started.
+    pub attempts: u64,
+    /// Retries which succeeded.
+    pub successes: u64,
+    /// Retries which gave up because their backoff was exhausted.
+    pub exhaustions: u64,
+}
+
+impl Counters {
+    fn counter(&mut self, counter: Counter) -> &mut u64 {
+        match counter {
+            Counter::InProgress => &mut self.in_progress,
+            Counter::Attempts => &mut self.attempts,
+            Counter::Successes => &mut self.successes,
+            Counter::Exhaustions => &mut self.exhaustions,
+        }
+    }
+}
+
+/// Exponential moving averages of the outcome of recent attempts.
+///
+/// Recent attempts weigh more than older ones, so applications can react to the observed
+/// health of a dependency, like switching providers when the success rate drops.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Health {
+    /// Average time attempts took.
+    pub latency: Duration,
+    /// Average share of attempts which succeeded, between zero and one.
+    pub success_rate: f64,
+    /// Number of attempts the averages are based on.
+    pub samples: u64,
+}
+
+impl Health {
+    fn record(&mut self, latency: Duration, success: bool) {
+        let success = if success { 1.0 } else { 0.0 };
+        if self.samples == 0 {
+            self.latency = latency;
+            self.success_rate = success;
+        } else {
+            let latency = latency.as_secs_f64() * EWMA_ALPHA
+                + self.latency.as_secs_f64() * (1.0 - EWMA_ALPHA);
+            self.latency = Duration::from_secs_f64(latency);
+            self.success_rate = success * EWMA_ALPHA + self.success_rate * (1.0 - EWMA_ALPHA);
+        }
+        self.samples += 1;
+    }
+}
+
+/// The key the statistics of retries registered under `name` are kept under.
+///
+/// Labels from `Retryable::metric_labels` are appended like `fetch{endpoint="eu-1"}`, so
+/// every combination of label values is counted apart. Without labels this is `name`.
+pub fn series(name: &str, labels: &[(&str, String)]) -> String {
+    if labels.is_empty() {
+        return name.to_string();
+    }
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(key, value)| format!("{}={:?}", key, value))
+        .collect();
+    format!("{}{{{}}}", name, labels.join(","))
+}
+
+/// The health of the attempts of all retries registered under the series `name`.
+pub fn health(name: &str) -> Option<Health> {
+    NAMED.lock().unwrap().health.get(name).copied()
+}
+
+/// Aggregated retry statistics at the time of the snapshot.
+///
+/// Serializable with the `serde` feature, to dump it into logs or health checks.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StatsSnapshot {
+    /// Counters over all tracked retries.
+    pub total: Counters,
+    /// Average number of attempts per second since the first tracked retry was polled.
+    pub attempts_per_sec: f64,
+    /// Counters per series of the retries registered with `Retry::register`, see `series`.
+    pub by_name: BTreeMap<String, Counters>,
+}
+
+/// Take a snapshot of the statistics of all tracked retries in this process.
+///
+/// Only retries opting in with `Retry::track_stats` or `Retry::register` are counted, from
+/// the time they are first polled.
+pub fn snapshot() -> StatsSnapshot {
+    let total = TOTAL.load();
+    let attempts_per_sec = match STARTED.get() {
+        Some(started) => {
+            let elapsed = started.elapsed().as_secs_f64();
+            if elapsed > 0.0 {
+                total.attempts as f64 / elapsed
+            } else {
+                0.0
+            }
+        }
+        None => 0.0,
+    };
+    StatsSnapshot {
+        total,
+        attempts_per_sec,
+        by_name: NAMED.lock().unwrap().by_name.clone(),
+    }
+}
+
+/// Records the statistics of one `Retry`.
+///
+/// The health is always recorded, the global counters only once tracking is enabled.
+pub(crate) struct Tracker {
+    name: Option<String>,
+    enabled: bool,
+    /// Whether the retry was counted as in progress.
+    started: bool,
+    finished: bool,
+    health: Health,
+}
+
+impl Tracker {
+    pub(crate) fn new() -> Self {
+        Tracker {
+            name: None,
+            enabled: false,
+            started: false,
+            finished: false,
+            health: Health::default(),
+        }
+    }
+
+    pub(crate) fn enable(&mut self) {
+        self.enabled = true;
+    }
+
+    pub(crate) fn set_name(&mut self, name: String) {
+        self.enabled = true;
+        if self.started && !self.finished {
+            let mut named = NAMED.lock().unwrap();
+            if let Some(old) = self.name.as_deref() {
+                if let Some(counters) = named.by_name.get_mut(old) {
+                    counters.in_progress -= 1;
+                }
+            }
+            named.by_name.entry(name.clone()).or_default().in_progress += 1;
+        }
+        self.name = Some(name);
+    }
+
+    /// Count the retry as in progress, called on every poll.
+    pub(crate) fn polled(&mut self) {
+        if self.enabled && !self.started && !self.finished {
+            self.started = true;
+            STARTED.get_or_init(Instant::now);
+            update(self.name.as_deref(), Counter::InProgress, false);
+        }
+    }
+
+    pub(crate) fn attempt(&self) {
+        if self.started {
+            update(self.name.as_deref(), Counter::Attempts, false);
+        }
+    }
+
+    pub(crate) fn attempt_finished(&mut self, latency: Duration, success: bool) {
+        self.health.record(latency, success);
+        if let Some(name) = &self.name {
+            let mut named = NAMED.lock().unwrap();
+            let health = named.health.entry(name.clone()).or_default();
+            health.record(latency, success);
+        }
+    }
+
+    pub(crate) fn health(&self) -> Health {
+        self.health
+    }
+
+    pub(crate) fn success(&mut self) {
+        self.finish(Some(Counter::Successes));
+    }
+
+    pub(crate) fn exhausted(&mut self) {
+        self.finish(Some(Counter::Exhaustions));
+    }
+
+    pub(crate) fn cancelled(&mut self) {
+        self.finish(None);
+    }
+
+    fn finish(&mut self, outcome: Option<Counter>) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+        if !self.started {
+            return;
+        }
+        update(self.name.as_deref(), Counter::InProgress, true);
+        if let Some(outcome) = outcome {
+            update(self.name.as_deref(), outcome, false);
+        }
+    }
+}
+
+impl Drop for Tracker {
+    fn drop(&mut self) {
+        self.cancelled();
+    }
+}
@@ -0,0 +1,218 @@
+use crate::{Classifier, ErrorKind};
+use std::{error::Error, io};
+
+/// A failure of the transport underneath a request, as opposed to an error response.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TransportFailure {
+    /// Resolving the host name failed.
+    Dns,
+    /// The connection was refused.
+    ConnectRefused,
+    /// The connection was reset, aborted or closed halfway.
+    ConnectionReset,
+    /// The TLS handshake failed.
+    Tls,
+    /// The HTTP/2 connection was shut down with a GOAWAY frame.
+    GoAway,
+    /// Connecting, or reading the response or its body, timed out.
+    Timeout,
+}
+
+/// Find the transport failure behind `error`, if any.
+///
+/// Walks the `source` chain of `error` looking for `io::Error`s and for the messages the
+/// errors of HTTP clients like hyper and reqwest use for DNS, TLS and GOAWAY failures. These
+/// are matched by message as their types are private to those crates.
+pub fn transport_failure(error: &(dyn Error + 'static)) -> Option<TransportFailure> {
+    let mut next = Some(error);
+    while let Some(error) = next {
+        if let Some(failure) = classify_one(error) {
+            return Some(failure);
+        }
+        next = error.source();
+    }
+    None
+}
+
+fn classify_one(error: &(dyn Error + 'static)) -> Option<TransportFailure> {
+    let message = error.to_string().to_lowercase();
+    let contains = |patterns: &[&str]| patterns.iter().any(|p| message.contains(p));
+    if contains(&[
+        "dns error",
+        "failed to lookup address",
+        "name or service not known",
+        "no such host",
+    ]) {
+        return Some(TransportFailure::Dns);
+    }
+    if contains(&["goaway", "go away"]) {
+        return Some(TransportFailure::GoAway);
+    }
+    if contains(&["tls", "ssl", "certificate", "handshake"]) {
+        return Some(TransportFailure::Tls);
+    }
+    if let Some(error) = error.downcast_ref::<io::Error>() {
+        match error.kind() {
+            io::ErrorKind::ConnectionRefused => return Some(TransportFailure::ConnectRefused),
+            io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::UnexpectedEof => return Some(TransportFailure::ConnectionReset),
+            io::ErrorKind::TimedOut => return Some(TransportFailure::Timeout),
+            _ => {}
+        }
+    }
+    if contains(&["timed out", "timeout"]) {
+        return Some(TransportFailure::Timeout);
+    }
+    None
+}
+
+/// Classifies errors by the transport failure behind them.
+///
+/// Errors without a transport failure are permanent, combine this classifier with one for
+/// error responses to retry those too. By default TLS failures are permanent, as a bad
+/// certificate won't fix itself, and all other transport failures are transient.
+#[derive(Clone, Debug)]
+pub struct TransportClassifier {
+    decisions: Vec<(TransportFailure, ErrorKind)>,
+}
+
+impl TransportClassifier {
+    pub fn new() -> Self {
+        TransportClassifier {
+            decisions: vec![(TransportFailure::Tls, ErrorKind::Permanent)],
+        }
+    }
+
+    /// Override the decision for a kind of transport failure.
+    pub fn decide(mut self, failure: TransportFailure, kind: ErrorKind) -> Self {
+        self.decisions.retain(|(f, _)| *f != failure);
+        self.decisions.push((failure, kind));
+        self
+    }
+}
+
+impl Default for TransportClassifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E> Classifier<E> for TransportClassifier
+where
+    E: Error + 'static,
+{
+    fn classify(&self, error: &E) -> ErrorKind {
+        let failure = match transport_failure(error) {
+            Some(failure) => failure,
+            None => return ErrorKind::Permanent,
+        };
+        self.decisions
+            .iter()
+            .find(|(f, _)| *f == failure)
+            .map(|(_, kind)| *kind)
+            .unwrap_or(ErrorKind::Transient)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block_on, instant, retry, Backoff};
+    use std::{
+        fmt,
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        },
+    };
+
+    /// Mimics the error of an HTTP client wrapping the underlying cause.
+    #[derive(Debug)]
+    struct ClientError {
+        message: &'static str,
+        source: Option<io::Error>,
+    }
+
+    impl fmt::Display for ClientError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(self.message)
+        }
+    }
+
+    impl Error for ClientError {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            self.source.as_ref().map(|e| e as _)
+        }
+    }
+
+    fn client_error(message: &'static str, source: Option<io::Error>) -> ClientError {
+        ClientError { message, source }
+    }
+
+    #[test]
+    fn test_transport_failure() {
+        let refused = io::Error::from(io::ErrorKind::ConnectionRefused);
+        let err = client_error("error sending request", Some(refused));
+        assert_eq!(
+            transport_failure(&err),
+            Some(TransportFailure::ConnectRefused)
+        );
+
+        let dns = io::Error::other("failed to lookup address information");
+        let err = client_error("client error (Connect)", Some(dns));
+        assert_eq!(transport_failure(&err), Some(TransportFailure::Dns));
+
+        let err = client_error("http2 error: connection error received: GOAWAY", None);
+        assert_eq!(transport_failure(&err), Some(TransportFailure::GoAway));
+
+        let err = client_error("invalid peer certificate: Expired", None);
+        assert_eq!(transport_failure(&err), Some(TransportFailure::Tls));
+
+        let reset = io::Error::from(io::ErrorKind::UnexpectedEof);
+        let err = client_error("error reading a body from connection", Some(reset));
+        assert_eq!(
+            transport_failure(&err),
+            Some(TransportFailure::ConnectionReset)
+        );
+
+        let err = client_error("operation timed out", None);
+        assert_eq!(transport_failure(&err), Some(TransportFailure::Timeout));
+
+        let err = client_error("HTTP status client error (404 Not Found)", None);
+        assert_eq!(transport_failure(&err), None);
+    }
+
+    #[test]
+    fn test_transport_classifier() {
+        let classifier = TransportClassifier::new();
+        let reset = client_error("", Some(io::ErrorKind::ConnectionReset.into()));
+        let tls = client_error("tls handshake eof", None);
+        let status = client_error("404 Not Found", None);
+        assert_eq!(classifier.classify(&reset), ErrorKind::Transient);
+        assert_eq!(classifier.classify(&tls), ErrorKind::Permanent);
+        assert_eq!(classifier.classify(&status), ErrorKind::Permanent);
+
+        let classifier = classifier.decide(TransportFailure::Tls, ErrorKind::Transient);
+        assert_eq!(classifier.classify(&tls), ErrorKind::Transient);
+
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+        let fut = retry(
+            move || {
+                let call = counter.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    match call {
+                        0 => Err::<(), _>(client_error("", Some(io::ErrorKind::TimedOut.into()))),
+                        _ => Err(client_error("invalid peer certificate", None)),
+                    }
+                }
+            },
+            instant().num_attempts(5),
+        )
+        .classifier(TransportClassifier::new());
+        assert!(block_on(fut).is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,272 @@
+use std::{
+    convert::TryFrom,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock, Weak,
+    },
+    task::{Context, Poll, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// A hashed timer wheel shared by many retries.
+///
+/// Every `Retry` normally arms its own timer for each wait. With tens of thousands of
+/// concurrent retries that is wasteful, a `Retry` using a timer wheel (see
+/// `Retry::timer_wheel`) registers its wakeups with the wheel instead. One background thread
+/// advances the wheel every `tick` and wakes all sleeps that became due in batch, so waits are
+/// rounded up to a multiple of the tick.
+///
+/// The background thread parks while no sleeps are pending and stops once all handles to the
+/// wheel are dropped.
+#[derive(Clone, Debug)]
+pub struct TimerWheel {
+    inner: Arc<Wheel>,
+}
+
+#[derive(Debug)]
+struct Wheel {
+    start: Instant,
+    tick: Duration,
+    state: Mutex<WheelState>,
+    thread: OnceLock<thread::Thread>,
+}
+
+impl Drop for Wheel {
+    fn drop(&mut self) {
+        // Let a parked background thread notice the wheel is gone.
+        if let Some(thread) = self.thread.get() {
+            thread.unpark();
+        }
+    }
+}
+
+#[derive(Debug)]
+struct WheelState {
+    /// The last tick whose slot was processed.
+    processed: u64,
+    /// The number of sleeps in the slots, the background thread parks when there are none.
+    pending: usize,
+    slots: Vec<Vec<(u64, Weak<SleepState>)>>,
+}
+
+#[derive(Debug, Default)]
+struct SleepState {
+    fired: AtomicBool,
+    waker: Mutex<Option<Waker>>,
+}
+
+impl SleepState {
+    fn fire(&self) {
+        self.fired.store(true, Ordering::SeqCst);
+        if let Some(waker) = self.waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    }
+}
+
+impl TimerWheel {
+    /// Create a wheel advancing every `tick` with `slots` slots.
+    ///
+    /// Sleeps longer than `tick * slots` go around the wheel more than once, pick the number
+    /// of slots to cover the common delays.
+    pub fn new(tick: Duration, slots: usize) -> Self {
+        assert!(
+            tick > Duration::from_secs(0),
+            "tick must be larger than zero"
+        );
+        assert!(slots > 0, "slots must be larger than zero");
+        let inner = Arc::new(Wheel {
+            start: Instant::now(),
+            tick,
+            state: Mutex::new(WheelState {
+                processed: 0,
+                pending: 0,
+                slots: (0..slots).map(|_| Vec::new()).collect(),
+            }),
+            thread: OnceLock::new(),
+        });
+        let wheel = Arc::downgrade(&inner);
+        let handle = thread::Builder::new()
+            .name("retry-timer-wheel".into())
+            .spawn(move || run(wheel))
+            .expect("failed to spawn timer wheel thread");
+        let _ = inner.thread.set(handle.thread().clone());
+        TimerWheel { inner }
+    }
+
+    /// Make a future which completes after `duration`, rounded up to the next tick.
+    pub fn sleep(&self, duration: Duration) -> WheelSleep {
+        let wheel = &self.inner;
+        let state = Arc::new(SleepState::default());
+        let elapsed = wheel.start.elapsed() + duration;
+        let deadline = ticks(elapsed, wheel.tick);
+
+        let mut wheel_state = wheel.state.lock().unwrap();
+        if deadline <= wheel_state.processed {
+            state.fired.store(true, Ordering::SeqCst);
+        } else {
+            let slot = (deadline % wheel_state.slots.len() as u64) as usize;
+            wheel_state.slots[slot].push((deadline, Arc::downgrade(&state)));
+            wheel_state.pending += 1;
+            if wheel_state.pending == 1 {
+                if let Some(thread) = wheel.thread.get() {
+                    thread.unpark();
+                }
+            }
+        }
+        WheelSleep { state }
+    }
+}
+
+impl Default for TimerWheel {
+    /// A wheel with a 10ms tick and 512 slots, covering about 5s per round.
+    fn default() -> Self {
+        Self::new(Duration::from_millis(10), 512)
+    }
+}
+
+/// The number of ticks in `duration`, rounded up.
+fn ticks(duration: Duration, tick: Duration) -> u64 {
+    let nanos = duration.as_nanos().div_ceil(tick.as_nanos());
+    u64::try_from(nanos).unwrap_or(u64::MAX)
+}
+
+fn run(wheel: Weak<Wheel>) {
+    let mut next = 1u64;
+    loop {
+        let wheel = match wheel.upgrade() {
+            Some(wheel) => wheel,
+            None => return,
+        };
+        if wheel.state.lock().unwrap().pending == 0 {
+            // A sleep registered after the wheel was found empty unparks the thread, so its
+            // wakeup isn't lost even if it happens before the thread parks.
+            drop(wheel);
+            thread::park();
+            continue;
+        }
+        let due = wheel.start + Duration::from_nanos((wheel.tick.as_nanos() * next as u128) as u64);
+        let now = Instant::now();
+        if due > now {
+            drop(wheel);
+            thread::sleep(due - now);
+            continue;
+        }
+
+        let elapsed = wheel.start.elapsed().as_nanos() / wheel.tick.as_nanos();
+        let current = u64::try_from(elapsed).unwrap_or(u64::MAX).max(next);
+        let mut fired = Vec::new();
+        {
+            let mut state = wheel.state.lock().unwrap();
+            let state = &mut *state;
+            let len = state.slots.len() as u64;
+            let first = state.processed + 1;
+            // Going around more than once would only visit the same slots again.
+            for tick in first..=current.min(first + len - 1) {
+                let slot = &mut state.slots[(tick % len) as usize];
+                let before = slot.len();
+                slot.retain(|(deadline, sleep)| {
+                    if *deadline > current {
+                        return sleep.strong_count() > 0;
+                    }
+                    if let Some(sleep) = sleep.upgrade() {
+                        fired.push(sleep);
+                    }
+                    false
+                });
+                state.pending -= before - slot.len();
+            }
+            state.processed = current;
+        }
+        for sleep in fired {
+            sleep.fire();
+        }
+        next = current + 1;
+    }
+}
+
+/// Future returned by `TimerWheel::sleep`
+#[derive(Debug)]
+pub struct WheelSleep {
+    state: Arc<SleepState>,
+}
+
+impl Future for WheelSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.state.fired.load(Ordering::SeqCst) {
+            return Poll::Ready(());
+        }
+        *self.state.waker.lock().unwrap() = Some(ctx.waker().clone());
+        if self.state.fired.load(Ordering::SeqCst) {
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block_on, constant, retry, Backoff};
+    use std::sync::atomic::AtomicU32;
+
+    #[test]
+    fn test_timer_wheel() {
+        let wheel = TimerWheel::new(Duration::from_millis(5), 4);
+        let start = Instant::now();
+        let sleeps: Vec<_> = (0..100)
+            .map(|i| wheel.sleep(Duration::from_millis(i % 50)))
+            .collect();
+        for sleep in sleeps {
+            block_on(sleep);
+        }
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(49));
+        assert!(elapsed < Duration::from_secs(1));
+
+        block_on(wheel.sleep(Duration::from_secs(0)));
+        drop(wheel.sleep(Duration::from_millis(5)));
+    }
+
+    #[test]
+    fn test_idle_wheel_parks() {
+        let wheel = TimerWheel::new(Duration::from_millis(1), 4);
+        block_on(wheel.sleep(Duration::from_millis(5)));
+        let processed = wheel.inner.state.lock().unwrap().processed;
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(wheel.inner.state.lock().unwrap().processed, processed);
+
+        // A new sleep wakes the thread up again.
+        block_on(wheel.sleep(Duration::from_millis(5)));
+        assert!(wheel.inner.state.lock().unwrap().processed > processed);
+    }
+
+    #[test]
+    fn test_retry_timer_wheel() {
+        let wheel = TimerWheel::default();
+        let attempts = Arc::new(AtomicU32::new(0));
+        let counter = attempts.clone();
+        let start = Instant::now();
+        let fut = retry(
+            move || {
+                let attempt = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                async move {
+                    if attempt < 3 {
+                        Err(())
+                    } else {
+                        Ok(attempt)
+                    }
+                }
+            },
+            constant(Duration::from_millis(20)).num_attempts(5),
+        )
+        .timer_wheel(wheel);
+        assert_eq!(block_on(fut).unwrap(), 3);
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+}
@@ -0,0 +1,185 @@
+use crate::{retry, Backoff, Retryable};
+use std::{error::Error, fmt, future::Future, pin::Pin, sync::Arc};
+
+type StepFuture = Pin<Box<dyn Future<Output = bool> + Send>>;
+type Compensation = Box<dyn Fn() -> Pin<Box<dyn Future<Output = ()> + Send>> + Send>;
+
+struct Step {
+    name: String,
+    run: Box<dyn Fn() -> StepFuture + Send>,
+    compensate: Option<Compensation>,
+}
+
+/// A sequence of named steps, each retried with its own policy.
+///
+/// For multi-stage operations like provision, configure and verify. The steps run in order
+/// and a step only starts once the previous one succeeded. When a step gives up, `run`
+/// returns an error naming the step and the next call to `run` resumes at that step, after
+/// running its compensation if it has one.
+#[derive(Default)]
+pub struct Workflow {
+    steps: Vec<Step>,
+    completed: usize,
+    failed: bool,
+}
+
+impl Workflow {
+    pub fn new() -> Self {
+        Workflow::default()
+    }
+
+    /// Add a step retrying `task` with backoffs made by `backoff`.
+    ///
+    /// `backoff` is called every time the step is started, so a resumed step gets a fresh
+    /// backoff.
+    pub fn step<R, S, F>(mut self, name: impl Into<String>, task: R, backoff: F) -> Self
+    where
+        R: Retryable + Send + Sync + 'static,
+        R::Future: Send,
+        R::Item: Send,
+        R::Error: Send,
+        S: Backoff + 'static,
+        F: Fn() -> S + Send + 'static,
+    {
+        let task = Arc::new(task);
+        self.steps.push(Step {
+            name: name.into(),
+            run: Box::new(move || {
+                let fut = retry(task.clone(), backoff());
+                Box::pin(async move { fut.await.is_ok() })
+            }),
+            compensate: None,
+        });
+        self
+    }
+
+    /// Undo the partial effects of the last added step before it's resumed after a failure.
+    pub fn compensate<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let step = self
+            .steps
+            .last_mut()
+            .expect("compensate must follow a step");
+        step.compensate = Some(Box::new(move || Box::pin(f())));
+        self
+    }
+
+    /// The number of steps which succeeded so far.
+    pub fn completed(&self) -> usize {
+        self.completed
+    }
+
+    /// Run the remaining steps in order.
+    pub async fn run(&mut self) -> Result<(), WorkflowError> {
+        while let Some(step) = self.steps.get(self.completed) {
+            if self.failed {
+                if let Some(compensate) = &step.compensate {
+                    tracing::info!(step = %step.name, "compensating failed step");
+                    compensate().await;
+                }
+            }
+            if !(step.run)().await {
+                self.failed = true;
+                return Err(WorkflowError {
+                    step: step.name.clone(),
+                    index: self.completed,
+                });
+            }
+            self.failed = false;
+            self.completed += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Error returned by `Workflow::run` when a step gave up.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WorkflowError {
+    /// The name of the step which gave up.
+    pub step: String,
+    /// The index of the step which gave up.
+    pub index: usize,
+}
+
+impl fmt::Display for WorkflowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "workflow step `{}` gave up", self.step)
+    }
+}
+
+impl Error for WorkflowError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block_on, instant};
+    use std::sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Mutex,
+    };
+
+    #[test]
+    fn test_workflow() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let healthy = Arc::new(AtomicBool::new(false));
+        let configures = Arc::new(AtomicU32::new(0));
+
+        let step = |name: &'static str| {
+            let log = log.clone();
+            move || {
+                log.lock().unwrap().push(name);
+                async { Ok::<_, ()>(()) }
+            }
+        };
+        let configure = {
+            let log = log.clone();
+            let healthy = healthy.clone();
+            let configures = configures.clone();
+            move || {
+                configures.fetch_add(1, Ordering::SeqCst);
+                let ok = healthy.load(Ordering::SeqCst);
+                if ok {
+                    log.lock().unwrap().push("configure");
+                }
+                async move {
+                    if ok {
+                        Ok(())
+                    } else {
+                        Err("unhealthy")
+                    }
+                }
+            }
+        };
+        let undo = {
+            let log = log.clone();
+            move || {
+                log.lock().unwrap().push("undo configure");
+                async {}
+            }
+        };
+
+        let mut workflow = Workflow::new()
+            .step("provision", step("provision"), || instant().num_attempts(2))
+            .step("configure", configure, || instant().num_attempts(2))
+            .compensate(undo)
+            .step("verify", step("verify"), instant);
+
+        let err = block_on(workflow.run()).unwrap_err();
+        assert_eq!(err.step, "configure");
+        assert_eq!(err.index, 1);
+        assert_eq!(workflow.completed(), 1);
+        assert_eq!(configures.load(Ordering::SeqCst), 2);
+
+        healthy.store(true, Ordering::SeqCst);
+        block_on(workflow.run()).unwrap();
+        assert_eq!(workflow.completed(), 3);
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["provision", "undo configure", "configure", "verify"]
+        );
+        block_on(workflow.run()).unwrap();
+    }
+}